@@ -14,7 +14,19 @@ while read -r version; do
     version_dir="${dir}/${version}"
     mkdir -p "${version_dir}"
     # TODO other archs
-    packages_url="${base_url}${version}/main/binary-amd64/Packages.gz"
-    curl -o "${version_dir}/Packages.gz" "${packages_url}"
-    gunzip "${version_dir}/Packages.gz"
+    packages_path="main/binary-amd64/Packages.gz"
+    mkdir -p "${version_dir}/$(dirname "${packages_path}")"
+    curl -o "${version_dir}/Release" "${base_url}${version}/Release"
+    curl -o "${version_dir}/${packages_path}" "${base_url}${version}/${packages_path}"
+    # Verify the download against the SHA256 table of the Release file.
+    expected=$(awk -v path="${packages_path}" \
+        '/^[^ ]/ { sha256 = ($1 == "SHA256:") } sha256 && $3 == path { print $1 }' \
+        "${version_dir}/Release")
+    if ! echo "${expected}  ${version_dir}/${packages_path}" | sha256sum --check --quiet -; then
+        echo "Checksum mismatch for ${version} ${packages_path}" >&2
+        exit 1
+    fi
+    # The directory is a partial mirror of the suite, loaded by load_mirror. The tests
+    # read an uncompressed copy of its Packages file.
+    gunzip -c "${version_dir}/${packages_path}" > "${version_dir}/Packages"
 done < "${versions_file}"
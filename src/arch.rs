@@ -0,0 +1,121 @@
+/// Architecture tuples as `abi-libc-os-cpu`, following dpkg's tupletable.
+const TUPLES: &[(&str, &str)] = &[
+    ("amd64", "base-gnu-linux-amd64"),
+    ("arm64", "base-gnu-linux-arm64"),
+    ("armel", "eabi-gnu-linux-arm"),
+    ("armhf", "eabihf-gnu-linux-arm"),
+    ("i386", "base-gnu-linux-i386"),
+    ("loong64", "base-gnu-linux-loong64"),
+    ("mips", "base-gnu-linux-mips"),
+    ("mipsel", "base-gnu-linux-mipsel"),
+    ("mips64", "abi64-gnu-linux-mips64"),
+    ("mips64el", "abi64-gnu-linux-mips64el"),
+    ("powerpc", "base-gnu-linux-powerpc"),
+    ("ppc64", "base-gnu-linux-ppc64"),
+    ("ppc64el", "base-gnu-linux-ppc64el"),
+    ("riscv64", "base-gnu-linux-riscv64"),
+    ("s390x", "base-gnu-linux-s390x"),
+    ("alpha", "base-gnu-linux-alpha"),
+    ("hppa", "base-gnu-linux-hppa"),
+    ("ia64", "base-gnu-linux-ia64"),
+    ("m68k", "base-gnu-linux-m68k"),
+    ("sh4", "base-gnu-linux-sh4"),
+    ("sparc64", "base-gnu-linux-sparc64"),
+    ("x32", "x32-gnu-linux-amd64"),
+    ("hurd-i386", "base-gnu-hurd-i386"),
+    ("hurd-amd64", "base-gnu-hurd-amd64"),
+    ("kfreebsd-amd64", "base-gnu-kfreebsd-amd64"),
+    ("kfreebsd-i386", "base-gnu-kfreebsd-i386"),
+    ("musl-linux-amd64", "base-musl-linux-amd64"),
+    ("musl-linux-arm64", "base-musl-linux-arm64"),
+    ("musl-linux-i386", "base-musl-linux-i386"),
+];
+
+/// The `[abi, libc, os, cpu]` tuple of an architecture, if it is known.
+pub fn tuple(arch: &str) -> Option<[&'static str; 4]> {
+    let (_, tuple) = TUPLES.iter().find(|(name, _)| *name == arch)?;
+    let mut parts = tuple.splitn(4, '-');
+    Some([parts.next()?, parts.next()?, parts.next()?, parts.next()?])
+}
+
+/// Whether an architecture matches a name or wildcard such as `linux-any` or `any-amd64`.
+///
+/// Wildcards are expanded to a full `abi-libc-os-cpu` tuple by prefixing `any` parts, so
+/// `linux-any` stands for `any-any-linux-any`.
+pub fn matches(arch: &str, wildcard: &str) -> bool {
+    if arch == wildcard {
+        return true;
+    }
+    if arch == "all" || wildcard == "all" {
+        return false;
+    }
+    if wildcard == "any" {
+        return true;
+    }
+    let Some(tuple) = tuple(arch) else {
+        return false;
+    };
+    let parts: Vec<&str> = wildcard.split('-').collect();
+    if parts.len() > 4 || !parts.contains(&"any") {
+        return false;
+    }
+    let padding = 4 - parts.len();
+    parts
+        .iter()
+        .enumerate()
+        .all(|(i, part)| *part == "any" || *part == tuple[padding + i])
+}
+
+/// Evaluate an architecture restriction list, as in `foo [amd64 i386]` or `foo [!hurd-any]`.
+///
+/// A list of plain entries matches if any of them does; a list of negated entries
+/// matches if none of them does.
+pub fn matches_restriction<S: AsRef<str>>(restrictions: &[S], arch: &str) -> bool {
+    let mut positive = false;
+    let mut matched = false;
+    for restriction in restrictions {
+        match restriction.as_ref().strip_prefix('!') {
+            Some(negated) => {
+                if matches(arch, negated) {
+                    return false;
+                }
+            }
+            None => {
+                positive = true;
+                matched |= matches(arch, restriction.as_ref());
+            }
+        }
+    }
+    matched || !positive
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wildcards() {
+        assert!(matches("amd64", "any"));
+        assert!(matches("amd64", "linux-any"));
+        assert!(matches("amd64", "any-amd64"));
+        assert!(matches("x32", "any-amd64"));
+        assert!(matches("armhf", "any-arm"));
+        assert!(matches("hurd-i386", "hurd-any"));
+        assert!(matches("musl-linux-amd64", "musl-any-any"));
+        assert!(!matches("hurd-i386", "linux-any"));
+        assert!(!matches("i386", "any-amd64"));
+        assert!(!matches("all", "any"));
+    }
+
+    #[test]
+    fn test_restriction_lists() {
+        assert!(matches_restriction(&["amd64", "i386"], "i386"));
+        assert!(!matches_restriction(&["amd64", "i386"], "arm64"));
+        assert!(matches_restriction(&["!hurd-any"], "amd64"));
+        assert!(!matches_restriction(
+            &["!hurd-any", "!kfreebsd-any"],
+            "kfreebsd-amd64"
+        ));
+        assert!(matches_restriction::<&str>(&[], "amd64"));
+    }
+}
@@ -0,0 +1,123 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+/// Compression formats of repository index files, detected by their magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Bzip2,
+    Zstd,
+    Lz4,
+}
+
+impl Compression {
+    /// Detect the compression format from the first bytes of a file.
+    pub fn detect(magic: &[u8]) -> Self {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Compression::Gzip
+        } else if magic.starts_with(&[0xfd, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if magic.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Compression::Zstd
+        } else if magic.starts_with(&[0x04, 0x22, 0x4d, 0x18]) {
+            Compression::Lz4
+        } else {
+            Compression::None
+        }
+    }
+
+    /// The file extension apt uses for the format, e.g. `Packages.xz`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Xz => ".xz",
+            Compression::Bzip2 => ".bz2",
+            Compression::Zstd => ".zst",
+            Compression::Lz4 => ".lz4",
+        }
+    }
+}
+
+/// Wrap a reader so that it yields the decompressed content of its input, detecting the
+/// compression format by its magic bytes. Uncompressed input is passed through.
+pub fn decompress<'a, R: BufRead + 'a>(
+    mut reader: R,
+) -> io::Result<(Compression, Box<dyn Read + 'a>)> {
+    let compression = Compression::detect(reader.fill_buf()?);
+    let reader: Box<dyn Read + 'a> = match compression {
+        Compression::None => Box::new(reader),
+        Compression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(reader)),
+        Compression::Xz => Box::new(xz2::bufread::XzDecoder::new_multi_decoder(reader)),
+        Compression::Bzip2 => Box::new(bzip2::bufread::MultiBzDecoder::new(reader)),
+        Compression::Zstd => {
+            Box::new(ruzstd::decoding::StreamingDecoder::new(reader).map_err(io::Error::other)?)
+        }
+        Compression::Lz4 => Box::new(lz4_flex::frame::FrameDecoder::new(reader)),
+    };
+    Ok((compression, reader))
+}
+
+/// Open a possibly compressed file for reading its decompressed content.
+pub fn open<P: AsRef<Path>>(path: P) -> io::Result<(Compression, Box<dyn Read>)> {
+    decompress(BufReader::new(File::open(path)?))
+}
+
+/// Read the decompressed content of a possibly compressed file into a string.
+pub fn read_to_string<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut content = String::new();
+    open(path)?.1.read_to_string(&mut content)?;
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::io::Write;
+
+    use super::*;
+
+    const PACKAGES: &[u8] = b"Package: hello\nVersion: 2.10-2\n";
+
+    fn roundtrip(compressed: Vec<u8>, expected: Compression) -> Result<(), Box<dyn Error>> {
+        let (compression, mut reader) = decompress(&compressed[..])?;
+        assert_eq!(compression, expected);
+        let mut content = Vec::new();
+        reader.read_to_end(&mut content)?;
+        assert_eq!(content, PACKAGES);
+        Ok(())
+    }
+
+    #[test]
+    fn test_decompress() -> Result<(), Box<dyn Error>> {
+        roundtrip(PACKAGES.to_vec(), Compression::None)?;
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(PACKAGES)?;
+        roundtrip(gz.finish()?, Compression::Gzip)?;
+
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 6);
+        xz.write_all(PACKAGES)?;
+        roundtrip(xz.finish()?, Compression::Xz)?;
+
+        let mut bz2 = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        bz2.write_all(PACKAGES)?;
+        roundtrip(bz2.finish()?, Compression::Bzip2)?;
+
+        let zst = ruzstd::encoding::compress_to_vec(
+            PACKAGES,
+            ruzstd::encoding::CompressionLevel::Fastest,
+        );
+        roundtrip(zst, Compression::Zstd)?;
+
+        let mut lz4 = lz4_flex::frame::FrameEncoder::new(Vec::new());
+        lz4.write_all(PACKAGES)?;
+        roundtrip(lz4.finish()?, Compression::Lz4)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,341 @@
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use crate::compression::decompress;
+use crate::deb822::paragraphs;
+use crate::debian_version::DebianVersion;
+use crate::error::{Diagnostics, ParseError, ParseErrorKind};
+use crate::index::{Index, Origin};
+use crate::parse::{add_debian_package, debian_package_from_paragraph, DebianPackage};
+
+/// Magic string of ar archives, which `.deb` files are.
+const AR_MAGIC: &[u8] = b"!<arch>\n";
+
+fn invalid(message: String) -> ParseError {
+    ParseError::new(ParseErrorKind::InvalidValue(message))
+}
+
+/// Find the `control` file in a `control.tar` archive, whatever its compression.
+fn control_from_tar<R: Read>(reader: R) -> Result<Option<String>, ParseError> {
+    let (_, reader) = decompress(BufReader::new(reader))?;
+    let mut archive = tar::Archive::new(reader);
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        // Members are usually named `./control`.
+        let path = entry.path()?;
+        if path.strip_prefix(".").unwrap_or(&path) == Path::new("control") {
+            let mut control = String::new();
+            entry
+                .read_to_string(&mut control)
+                .map_err(|e| match e.kind() {
+                    io::ErrorKind::InvalidData => ParseError::new(ParseErrorKind::InvalidUtf8),
+                    _ => ParseError::from(e),
+                })?;
+            return Ok(Some(control));
+        }
+    }
+    Ok(None)
+}
+
+/// Read the control file of a binary package from the content of its `.deb` file.
+///
+/// A `.deb` file is an ar archive holding `debian-binary`, then `control.tar` compressed
+/// with gzip, xz or zstd, or not at all, then the data of the package, which is not read.
+pub fn read_deb_control<R: Read>(mut reader: R) -> Result<String, ParseError> {
+    let mut magic = [0; AR_MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if magic != AR_MAGIC {
+        return Err(invalid("Not a .deb file".to_string()));
+    }
+    let mut header = [0; 60];
+    loop {
+        match reader.read_exact(&mut header) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            read => read?,
+        }
+        let field = |range: std::ops::Range<usize>| {
+            String::from_utf8_lossy(&header[range]).trim().to_string()
+        };
+        // GNU ar ends member names with a slash.
+        let name = field(0..16);
+        let name = name.trim_end_matches('/');
+        let size: u64 = field(48..58)
+            .parse()
+            .map_err(|_| invalid(format!("Invalid ar member header: {}", field(0..60))))?;
+        let mut member = reader.by_ref().take(size);
+        match name {
+            "debian-binary" => {
+                let mut version = String::new();
+                member.read_to_string(&mut version)?;
+                if !version.starts_with("2.") {
+                    return Err(invalid(format!(
+                        "Unsupported .deb format {}",
+                        version.trim()
+                    )));
+                }
+            }
+            name if name.starts_with("control.tar") => {
+                return control_from_tar(member)?
+                    .ok_or(ParseError::new(ParseErrorKind::MissingField("control")));
+            }
+            _ => {}
+        }
+        // Skip what is left of the member, and the padding to an even offset.
+        io::copy(&mut member, &mut io::sink())?;
+        if size % 2 == 1 {
+            io::copy(&mut reader.by_ref().take(1), &mut io::sink())?;
+        }
+    }
+    Err(invalid("No control.tar member".to_string()))
+}
+
+fn deb_package(path: &Path, diagnostics: &mut Diagnostics) -> Result<DebianPackage, ParseError> {
+    let file = fs::File::open(path)?;
+    let control = read_deb_control(BufReader::new(file))?;
+    let paragraph = paragraphs(&control)
+        .next()
+        .ok_or(ParseError::new(ParseErrorKind::MissingField("Package")))??;
+    debian_package_from_paragraph(&paragraph, 0, diagnostics)
+}
+
+/// Parse the control file of a `.deb` file.
+pub fn parse_deb<P: AsRef<Path>>(path: P) -> Result<DebianPackage, ParseError> {
+    let path = path.as_ref();
+    deb_package(path, &mut Diagnostics::default()).map_err(|e| e.in_file(path))
+}
+
+/// Add a package to an index, replacing the relationships of the version if it is already
+/// known, while keeping its origins.
+fn replace_debian_package(index: &mut Index, dp: DebianPackage, origin: &Origin) {
+    let name = index.qualify(&dp.package, dp.architecture.as_deref().unwrap_or_default());
+    let version = DebianVersion(dp.version.clone());
+    let replaced = index
+        .packages
+        .get_mut(&name)
+        .and_then(|versions| versions.remove(&version));
+    for virtual_name in replaced.iter().flat_map(|replaced| &replaced.provides) {
+        if let Some(providers) = index.providers.get_mut(virtual_name) {
+            providers.retain(|provider| provider.name != name || provider.version != version);
+        }
+    }
+    add_debian_package(index, dp, Some(origin));
+    let entry = index
+        .packages
+        .get_mut(&name)
+        .and_then(|versions| versions.get_mut(&version));
+    if let (Some(replaced), Some(entry)) = (replaced, entry) {
+        let added = std::mem::replace(&mut entry.origins, replaced.origins);
+        for origin in added {
+            if !entry.origins.contains(&origin) {
+                entry.origins.push(origin);
+            }
+        }
+    }
+}
+
+/// Add the package of a `.deb` file to an existing index, with the local origin, see
+/// [`Origin::local`].
+///
+/// Unlike with [`load_repository`](crate::parse::load_repository), a version already
+/// known from a repository is replaced by the package, as a locally built package may
+/// differ from the one of the same version in the archive. The version keeps its origins.
+pub fn load_deb<P: AsRef<Path>>(
+    index: &mut Index,
+    path: P,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ParseError> {
+    let path = path.as_ref();
+    let reported = diagnostics.warnings.len();
+    let dp = deb_package(path, diagnostics).map_err(|e| e.in_file(path));
+    for warning in &mut diagnostics.warnings[reported..] {
+        warning.path.get_or_insert(path.to_path_buf());
+    }
+    replace_debian_package(index, dp?, &Origin::local());
+    Ok(())
+}
+
+/// Add the packages of the `.deb` files of a directory, such as a build output directory,
+/// to an existing index, see [`load_deb`].
+///
+/// Files are read in alphabetical order. In lenient mode, files that cannot be read are
+/// skipped and reported as warnings. A directory without `.deb` files is an error, as it
+/// is more likely a mistake, such as a mirror without its Release file, than intended.
+pub fn load_deb_directory<P: AsRef<Path>>(
+    index: &mut Index,
+    dir: P,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ParseError> {
+    let dir = dir.as_ref();
+    let mut paths = Vec::new();
+    for entry in fs::read_dir(dir).map_err(|e| ParseError::from(e).in_file(dir))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|extension| extension == "deb") {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        let missing = io::Error::new(io::ErrorKind::NotFound, "No .deb files");
+        return Err(ParseError::from(missing).in_file(dir));
+    }
+    paths.sort();
+    for path in paths {
+        if let Err(e) = load_deb(index, &path, diagnostics) {
+            diagnostics.report(e)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::io::Write;
+
+    use super::*;
+    use crate::error::Mode;
+    use crate::parse::parse_debian_package;
+
+    /// An ar archive, with GNU style member names.
+    fn ar(members: &[(&str, Vec<u8>)]) -> Vec<u8> {
+        let mut archive = AR_MAGIC.to_vec();
+        for (name, data) in members {
+            let header = format!(
+                "{:<16}{:<12}{:<6}{:<6}{:<8}{:<10}`\n",
+                format!("{}/", name),
+                0,
+                0,
+                0,
+                100644,
+                data.len()
+            );
+            archive.extend_from_slice(header.as_bytes());
+            archive.extend_from_slice(data);
+            if data.len() % 2 == 1 {
+                archive.push(b'\n');
+            }
+        }
+        archive
+    }
+
+    fn control_tar(control: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(control.len() as u64);
+        header.set_mode(0o644);
+        // As written by dpkg-deb, which `set_path` would normalize to `control`.
+        header.as_old_mut().name[..9].copy_from_slice(b"./control");
+        header.set_cksum();
+        builder.append(&header, control.as_bytes())?;
+        Ok(builder.into_inner()?)
+    }
+
+    fn deb(control: &str, compression: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        let tar = control_tar(control)?;
+        let compressed = match compression {
+            ".gz" => {
+                let mut encoder =
+                    flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+                encoder.write_all(&tar)?;
+                encoder.finish()?
+            }
+            ".xz" => {
+                let mut encoder = xz2::write::XzEncoder::new(Vec::new(), 6);
+                encoder.write_all(&tar)?;
+                encoder.finish()?
+            }
+            ".zst" => ruzstd::encoding::compress_to_vec(
+                &tar[..],
+                ruzstd::encoding::CompressionLevel::Fastest,
+            ),
+            _ => tar,
+        };
+        Ok(ar(&[
+            ("debian-binary", b"2.0\n".to_vec()),
+            (&format!("control.tar{}", compression), compressed),
+            ("data.tar.xz", b"not read".to_vec()),
+        ]))
+    }
+
+    #[test]
+    fn test_read_deb_control() -> Result<(), Box<dyn Error>> {
+        let control =
+            "Package: hello\nVersion: 2.10-3+ci1\nArchitecture: amd64\nDepends: libc6 (>= 2.34)\n";
+        for compression in ["", ".gz", ".xz", ".zst"] {
+            let deb = deb(control, compression)?;
+            assert_eq!(read_deb_control(&deb[..])?, control);
+        }
+        assert!(read_deb_control(&b"Package: hello\n"[..]).is_err());
+        let unsupported = ar(&[("debian-binary", b"3.0\n".to_vec())]);
+        assert!(read_deb_control(&unsupported[..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_deb_directory() -> Result<(), Box<dyn Error>> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path();
+        fs::write(
+            dir.join("hello_2.10-3+ci1_amd64.deb"),
+            deb(
+                "Package: hello\nVersion: 2.10-3+ci1\nArchitecture: amd64\n",
+                ".xz",
+            )?,
+        )?;
+        fs::write(
+            dir.join("libhello_2.10-3+ci1_amd64.deb"),
+            deb(
+                "Package: libhello\nVersion: 2.10-3+ci1\nArchitecture: amd64\n",
+                ".zst",
+            )?,
+        )?;
+        fs::write(dir.join("broken_1_amd64.deb"), "not a deb")?;
+        fs::write(dir.join("hello.dsc"), "not a deb either")?;
+        let empty = dir.join("empty");
+        fs::create_dir_all(&empty)?;
+
+        // The archive package of the same version, which the local build replaces, and an
+        // older version, which it leaves alone.
+        let mut index = Index::new();
+        let archive = Origin {
+            suite: "bookworm".to_string(),
+            ..Origin::default()
+        };
+        add_debian_package(
+            &mut index,
+            parse_debian_package(
+                "Package: hello\nVersion: 2.10-3\nArchitecture: amd64\nProvides: greeter\n",
+            )?,
+            Some(&archive),
+        );
+        add_debian_package(
+            &mut index,
+            parse_debian_package(
+                "Package: hello\nVersion: 2.10-3+ci1\nArchitecture: amd64\nDepends: libold\nProvides: greeter\n",
+            )?,
+            Some(&archive),
+        );
+        let mut diagnostics = Diagnostics::new(Mode::Lenient);
+        load_deb_directory(&mut index, dir, &mut diagnostics)?;
+        let strict = load_deb_directory(&mut Index::new(), dir, &mut Diagnostics::default());
+        assert!(strict.is_err());
+        let nothing = load_deb_directory(&mut Index::new(), &empty, &mut Diagnostics::default());
+        assert!(nothing.is_err());
+        let version = DebianVersion("2.10-3+ci1".to_string());
+        assert_eq!(index.available_versions(&"libhello".to_string()).len(), 1);
+        assert_eq!(index.origins("hello", &version), [archive, Origin::local()]);
+        assert!(index.packages["hello"][&version].depends.is_empty());
+        assert!(index.packages["hello"][&version].provides.is_empty());
+        let providers: Vec<&DebianVersion> = index.providers["greeter"]
+            .iter()
+            .map(|provider| &provider.version)
+            .collect();
+        assert_eq!(providers, [&DebianVersion("2.10-3".to_string())]);
+        assert_eq!(diagnostics.warnings.len(), 1);
+        assert!(diagnostics.warnings[0]
+            .path
+            .as_ref()
+            .is_some_and(|path| path.ends_with("broken_1_amd64.deb")));
+        Ok(())
+    }
+}
@@ -0,0 +1,616 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use crate::debian_version::DebianVersion;
+use crate::error::{ParseError, ParseErrorKind};
+use crate::parse::{parse_dependency_field, Dependency};
+
+/// A field of a deb822 paragraph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    /// Field name with its original casing.
+    pub name: String,
+    /// Field value as written: the text after the colon with leading whitespace removed,
+    /// followed by each continuation line (including its leading whitespace) on its own line.
+    pub value: String,
+}
+
+/// A deb822 paragraph, i.e. a stanza of a control file, keeping its fields in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Paragraph {
+    fields: Vec<Field>,
+    /// The text the paragraph was parsed from, written back verbatim until it is modified.
+    raw: Option<String>,
+    /// The line each field starts on, or 0 if unknown.
+    lines: Vec<usize>,
+}
+
+/// Marker lines of an OpenPGP cleartext signature, as used by `InRelease` files.
+pub(crate) const PGP_SIGNED_MESSAGE: &str = "-----BEGIN PGP SIGNED MESSAGE-----";
+pub(crate) const PGP_SIGNATURE: &str = "-----BEGIN PGP SIGNATURE-----";
+
+/// The signed content of an OpenPGP cleartext signed message, or the input if it is not one.
+///
+/// The armor headers and the signature are dropped and dash-escaped lines are unescaped.
+pub fn signed_content(input: &str) -> String {
+    let mut lines = input
+        .lines()
+        .map(|line| line.strip_suffix('\r').unwrap_or(line));
+    if lines.clone().find(|line| !line.trim().is_empty()) != Some(PGP_SIGNED_MESSAGE) {
+        return input.to_string();
+    }
+    // Skip up to the marker, then the armor headers, which end at the first empty line.
+    lines.by_ref().find(|line| *line == PGP_SIGNED_MESSAGE);
+    lines.by_ref().find(|line| line.trim().is_empty());
+    let mut content = String::new();
+    for line in lines {
+        if line == PGP_SIGNATURE {
+            break;
+        }
+        content.push_str(line.strip_prefix("- ").unwrap_or(line));
+        content.push('\n');
+    }
+    content
+}
+
+/// A field of a [`ParagraphRef`], borrowed from the text it was parsed from where possible.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldRef<'a> {
+    pub name: &'a str,
+    /// The value as in [`Field::value`]. It is only copied when it cannot be borrowed,
+    /// i.e. for multiline values with CRLF line endings or interleaved comment lines.
+    pub value: Cow<'a, str>,
+    /// The line the field starts on, counted from 1, or 0 if unknown.
+    pub line: usize,
+}
+
+/// A deb822 paragraph borrowing its fields from the text it was parsed from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParagraphRef<'a> {
+    fields: Vec<FieldRef<'a>>,
+    raw: &'a str,
+}
+
+/// An iterator over the paragraphs of deb822 text, parsing one paragraph at a time.
+///
+/// Unlike [`parse_paragraphs`], signed input is not unwrapped. After a syntax error,
+/// iteration resumes at the next paragraph.
+pub struct Paragraphs<'a> {
+    content: &'a str,
+    offset: usize,
+    /// Lines and paragraphs consumed so far.
+    line: usize,
+    stanza: usize,
+}
+
+/// Iterate over the paragraphs of deb822 text without copying it.
+///
+/// Paragraphs are separated by lines that are empty or contain only whitespace, and both
+/// LF and CRLF line endings are accepted. Comment lines starting with `#` are skipped.
+pub fn paragraphs(content: &str) -> Paragraphs<'_> {
+    Paragraphs {
+        content,
+        offset: 0,
+        line: 0,
+        stanza: 0,
+    }
+}
+
+impl<'a> Paragraphs<'a> {
+    /// Skip the remaining lines of the paragraph after a syntax error.
+    fn fail(&mut self, kind: ParseErrorKind) -> ParseError {
+        let error = ParseError::new(kind)
+            .in_stanza(self.stanza)
+            .at(self.line, 1);
+        for raw_line in self.content[self.offset..].split_inclusive('\n') {
+            self.offset += raw_line.len();
+            self.line += 1;
+            if raw_line.trim().is_empty() {
+                break;
+            }
+        }
+        self.stanza += 1;
+        error
+    }
+}
+
+impl<'a> Iterator for Paragraphs<'a> {
+    type Item = Result<ParagraphRef<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let content = self.content;
+        let mut fields: Vec<FieldRef<'a>> = Vec::new();
+        // Byte range of the paragraph, from its first field to its last line,
+        // and the end of the value of the last field while it is borrowed.
+        let mut span = 0..0;
+        let mut value_end = 0;
+        for raw_line in content[self.offset..].split_inclusive('\n') {
+            let start = self.offset;
+            self.offset += raw_line.len();
+            self.line += 1;
+            let line = raw_line.trim_end_matches(['\n', '\r']);
+            if line.starts_with('#') {
+                continue;
+            }
+            if line.trim().is_empty() {
+                if fields.is_empty() {
+                    continue;
+                }
+                break;
+            }
+            if line.starts_with(' ') || line.starts_with('\t') {
+                // Continuation line.
+                let Some(field) = fields.last_mut() else {
+                    let kind = ParseErrorKind::ContinuationWithoutField(line.to_string());
+                    return Some(Err(self.fail(kind)));
+                };
+                match &mut field.value {
+                    Cow::Borrowed(value) if value_end + 1 == start => {
+                        let value_start = value_end - value.len();
+                        value_end = start + line.len();
+                        field.value = Cow::Borrowed(&content[value_start..value_end]);
+                    }
+                    value => {
+                        let value = value.to_mut();
+                        value.push('\n');
+                        value.push_str(line);
+                    }
+                }
+            } else if let Some(pos) = line.find(':') {
+                if fields.is_empty() {
+                    span.start = start;
+                }
+                let value = line[pos + 1..].trim_start();
+                value_end = start + line.len();
+                fields.push(FieldRef {
+                    name: line[..pos].trim(),
+                    value: Cow::Borrowed(value),
+                    line: self.line,
+                });
+            } else {
+                let kind = ParseErrorKind::LineWithoutColon(line.to_string());
+                return Some(Err(self.fail(kind)));
+            }
+            span.end = self.offset;
+        }
+        if fields.is_empty() {
+            return None;
+        }
+        self.stanza += 1;
+        Some(Ok(ParagraphRef {
+            fields,
+            raw: &content[span],
+        }))
+    }
+}
+
+/// An iterator over the paragraphs read from a stream, holding one paragraph at a time.
+pub struct ParagraphReader<R> {
+    reader: R,
+    buffer: String,
+    /// Lines and paragraphs read before the buffer.
+    line: usize,
+    stanza: usize,
+}
+
+impl<R: BufRead> ParagraphReader<R> {
+    pub fn new(reader: R) -> Self {
+        ParagraphReader {
+            reader,
+            buffer: String::new(),
+            line: 0,
+            stanza: 0,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ParagraphReader<R> {
+    type Item = Result<Paragraph, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.buffer.clear();
+        let mut has_fields = false;
+        let mut lines = 0;
+        loop {
+            let start = self.buffer.len();
+            match self.reader.read_line(&mut self.buffer) {
+                Ok(0) => break,
+                Ok(_) => lines += 1,
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                    let error = ParseError::new(ParseErrorKind::InvalidUtf8)
+                        .in_stanza(self.stanza)
+                        .at(self.line + lines + 1, 1);
+                    return Some(Err(error));
+                }
+                Err(e) => return Some(Err(e.into())),
+            }
+            let line = &self.buffer[start..];
+            if line.trim().is_empty() {
+                if has_fields {
+                    break;
+                }
+            } else if !line.starts_with('#') {
+                has_fields = true;
+            }
+        }
+        let mut paragraphs = paragraphs(&self.buffer);
+        let paragraph = paragraphs.next().map(|paragraph| match paragraph {
+            Ok(paragraph) => {
+                let mut paragraph = paragraph.to_paragraph();
+                for line in &mut paragraph.lines {
+                    *line += self.line;
+                }
+                Ok(paragraph)
+            }
+            Err(mut error) => {
+                error.stanza = Some(self.stanza);
+                error.line += self.line;
+                Err(error)
+            }
+        });
+        self.line += lines;
+        self.stanza += 1;
+        paragraph
+    }
+}
+
+impl<'a> ParagraphRef<'a> {
+    /// The fields of the paragraph, in order.
+    pub fn fields(&self) -> &[FieldRef<'a>] {
+        &self.fields
+    }
+
+    /// The text the paragraph was parsed from.
+    pub fn raw(&self) -> &'a str {
+        self.raw
+    }
+
+    /// Value of a field, looked up case-insensitively.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.name.eq_ignore_ascii_case(name))
+            .map(|field| field.value.as_ref())
+    }
+
+    /// Value of a folded field, with its lines joined by spaces. Single line values are
+    /// not copied.
+    pub fn get_folded(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.get(name).map(fold)
+    }
+
+    /// An owned copy of the paragraph.
+    pub fn to_paragraph(&self) -> Paragraph {
+        Paragraph {
+            lines: self.fields.iter().map(|field| field.line).collect(),
+            fields: self
+                .fields
+                .iter()
+                .map(|field| Field {
+                    name: field.name.to_string(),
+                    value: field.value.to_string(),
+                })
+                .collect(),
+            raw: Some(self.raw.to_string()),
+        }
+    }
+}
+
+/// Join the lines of a folded value with spaces.
+fn fold(value: &str) -> Cow<'_, str> {
+    if !value.contains('\n') {
+        return Cow::Borrowed(value.trim_end());
+    }
+    Cow::Owned(
+        value
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join(" "),
+    )
+}
+
+/// Parse deb822 text into its paragraphs.
+///
+/// This is [`paragraphs`] collected into owned paragraphs, with OpenPGP cleartext signed
+/// input reduced to its signed content first.
+pub fn parse_paragraphs(input: &str) -> Result<Vec<Paragraph>, ParseError> {
+    let content = signed_content(input);
+    paragraphs(&content)
+        .map(|paragraph| paragraph.map(|paragraph| paragraph.to_paragraph()))
+        .collect()
+}
+
+/// Write paragraphs as deb822 text, separated by empty lines.
+pub fn write_paragraphs<'a, I: IntoIterator<Item = &'a Paragraph>>(paragraphs: I) -> String {
+    paragraphs
+        .into_iter()
+        .map(|paragraph| paragraph.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl fmt::Display for Paragraph {
+    /// Paragraphs that were parsed and not modified since are written byte for byte as read,
+    /// other paragraphs one `Name: value` field per line.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(raw) = &self.raw {
+            write!(f, "{}", raw)?;
+            if !raw.ends_with('\n') {
+                writeln!(f)?;
+            }
+            return Ok(());
+        }
+        for field in &self.fields {
+            if field.value.is_empty() || field.value.starts_with('\n') {
+                writeln!(f, "{}:{}", field.name, field.value)?;
+            } else {
+                writeln!(f, "{}: {}", field.name, field.value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Paragraph {
+    /// A new paragraph with the given fields, in order.
+    pub fn new(fields: Vec<Field>) -> Self {
+        Paragraph {
+            lines: vec![0; fields.len()],
+            fields,
+            raw: None,
+        }
+    }
+
+    /// The fields of the paragraph, in order.
+    pub fn fields(&self) -> &[Field] {
+        &self.fields
+    }
+
+    /// A view of the paragraph borrowing its fields.
+    pub fn borrow(&self) -> ParagraphRef<'_> {
+        ParagraphRef {
+            fields: self
+                .fields
+                .iter()
+                .zip(&self.lines)
+                .map(|(field, &line)| FieldRef {
+                    name: &field.name,
+                    value: Cow::Borrowed(&field.value),
+                    line,
+                })
+                .collect(),
+            raw: self.raw.as_deref().unwrap_or_default(),
+        }
+    }
+
+    /// Raw value of a field, looked up case-insensitively.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.name.eq_ignore_ascii_case(name))
+            .map(|field| field.value.as_str())
+    }
+
+    /// Value of a folded field, such as Depends, with its lines joined by spaces.
+    pub fn get_folded(&self, name: &str) -> Option<String> {
+        self.get(name).map(|value| fold(value).into_owned())
+    }
+
+    /// Lines of a multiline field, such as Description or SHA256.
+    ///
+    /// The first line is the text following the colon, which may be empty. Continuation
+    /// lines have one leading space removed, and a lone `.` stands for an empty line.
+    pub fn get_lines(&self, name: &str) -> Option<Vec<String>> {
+        let value = self.get(name)?;
+        Some(
+            value
+                .split('\n')
+                .enumerate()
+                .map(|(i, line)| {
+                    if i == 0 {
+                        return line.trim_end().to_string();
+                    }
+                    let line = line.strip_prefix([' ', '\t']).unwrap_or(line).trim_end();
+                    if line == "." {
+                        String::new()
+                    } else {
+                        line.to_string()
+                    }
+                })
+                .collect(),
+        )
+    }
+
+    /// Value of a field parsed into a type, e.g. a [`DebianVersion`] or a number.
+    pub fn get_parsed<T: FromStr>(&self, name: &str) -> Option<Result<T, T::Err>> {
+        self.get_folded(name).map(|value| value.parse::<T>())
+    }
+
+    /// Value of a `yes`/`no` field such as Essential.
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        match self.get_folded(name)?.as_str() {
+            "yes" => Some(true),
+            "no" => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Items of a whitespace separated field, such as Architectures or Components.
+    pub fn get_words(&self, name: &str) -> Vec<String> {
+        self.get(name)
+            .into_iter()
+            .flat_map(str::split_whitespace)
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// Items of a comma separated field, such as Binary.
+    pub fn get_comma_list(&self, name: &str) -> Vec<String> {
+        self.get_folded(name)
+            .into_iter()
+            .flat_map(|value| {
+                value
+                    .split(',')
+                    .map(|item| item.trim().to_string())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|item| !item.is_empty())
+            .collect()
+    }
+
+    /// A relationship field such as Depends, Build-Depends or Provides.
+    pub fn get_relations(&self, name: &str) -> Result<Vec<Dependency>, ParseError> {
+        parse_dependency_field(&self.get_folded(name).unwrap_or_default())
+            .map_err(|e| e.in_field(name))
+    }
+
+    pub fn package(&self) -> Option<String> {
+        self.get_folded("Package")
+    }
+
+    pub fn version(&self) -> Option<DebianVersion> {
+        self.get_folded("Version").map(DebianVersion)
+    }
+
+    pub fn architecture(&self) -> Option<String> {
+        self.get_folded("Architecture")
+    }
+
+    /// The synopsis and extended description of the Description field.
+    pub fn description(&self) -> Option<(String, String)> {
+        let mut lines = self.get_lines("Description")?.into_iter();
+        let synopsis = lines.next().unwrap_or_default();
+        Some((synopsis, lines.collect::<Vec<_>>().join("\n")))
+    }
+
+    /// Set a field, replacing its value in place if present and appending it otherwise.
+    pub fn set(&mut self, name: &str, value: &str) {
+        self.raw = None;
+        match self
+            .fields
+            .iter_mut()
+            .find(|field| field.name.eq_ignore_ascii_case(name))
+        {
+            Some(field) => field.value = value.to_string(),
+            None => {
+                self.fields.push(Field {
+                    name: name.to_string(),
+                    value: value.to_string(),
+                });
+                self.lines.push(0);
+            }
+        }
+    }
+
+    /// Remove a field, returning its value.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let pos = self
+            .fields
+            .iter()
+            .position(|field| field.name.eq_ignore_ascii_case(name))?;
+        self.raw = None;
+        self.lines.remove(pos);
+        Some(self.fields.remove(pos).value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_paragraphs() -> Result<(), Box<dyn Error>> {
+        let input = "Package: hello\r\nVersion: 2.10-2\r\nDepends: libc6 (>= 2.14),\r\n zlib1g\r\nDescription: example package\r\n GNU hello prints a greeting.\r\n .\r\n It is an example.\r\n  \r\n# a comment\r\npackage: other\r\nVersion: 1.0\r\n";
+        let paragraphs = parse_paragraphs(input)?;
+        assert_eq!(paragraphs.len(), 2);
+        let hello = &paragraphs[0];
+        assert_eq!(hello.package(), Some("hello".to_string()));
+        assert_eq!(hello.version(), Some(DebianVersion("2.10-2".to_string())));
+        assert_eq!(hello.get_relations("depends")?.len(), 2);
+        assert_eq!(
+            hello.description(),
+            Some((
+                "example package".to_string(),
+                "GNU hello prints a greeting.\n\nIt is an example.".to_string()
+            ))
+        );
+        assert_eq!(
+            hello
+                .fields
+                .iter()
+                .map(|f| f.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["Package", "Version", "Depends", "Description"]
+        );
+        assert_eq!(paragraphs[1].fields()[0].name, "package");
+        assert_eq!(paragraphs[1].package(), Some("other".to_string()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_signed_content() -> Result<(), Box<dyn Error>> {
+        let input = "-----BEGIN PGP SIGNED MESSAGE-----\nHash: SHA256\n\nOrigin: Debian\nSuite: stable\nSHA256:\n 0123 42 main/binary-amd64/Packages\n- -not-a-field: escaped\n-----BEGIN PGP SIGNATURE-----\n\niQIzBAEBCAAdFiEE\n-----END PGP SIGNATURE-----\n";
+        let paragraphs = parse_paragraphs(input)?;
+        assert_eq!(paragraphs.len(), 1);
+        let release = &paragraphs[0];
+        assert_eq!(release.get("Origin"), Some("Debian"));
+        assert_eq!(
+            release.get_lines("SHA256"),
+            Some(vec![
+                "".to_string(),
+                "0123 42 main/binary-amd64/Packages".to_string()
+            ])
+        );
+        assert_eq!(release.get("-not-a-field"), Some("escaped"));
+
+        let leading = format!("\n\r\n{}", input);
+        assert_eq!(signed_content(&leading), signed_content(input));
+        assert!(signed_content(&leading).starts_with("Origin: Debian\n"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_paragraphs_borrowed() -> Result<(), Box<dyn Error>> {
+        let input = "Package: hello\nDepends: a,\n b\nBuild-Depends: c,\n# d,\n e\n\nPackage: other\r\nDepends: a,\r\n b\r\n";
+        let parsed = paragraphs(input).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(parsed.len(), 2);
+        let hello = &parsed[0];
+        assert!(matches!(hello.fields()[1].value, Cow::Borrowed("a,\n b")));
+        assert!(matches!(&hello.fields()[2].value, Cow::Owned(v) if v == "c,\n e"));
+        assert!(matches!(
+            hello.get_folded("package"),
+            Some(Cow::Borrowed("hello"))
+        ));
+        assert_eq!(parsed[1].get_folded("Depends").as_deref(), Some("a, b"));
+
+        let read = ParagraphReader::new(input.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(
+            read,
+            parsed.iter().map(|p| p.to_paragraph()).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_paragraphs() -> Result<(), Box<dyn Error>> {
+        let input = "Package:  hello\r\nDescription: example\r\n .\r\n# kept\r\n text  \r\n \t\r\nPackage: other\nVersion: 1.0\n";
+        let mut paragraphs = parse_paragraphs(input)?;
+        assert_eq!(
+            write_paragraphs(&paragraphs),
+            "Package:  hello\r\nDescription: example\r\n .\r\n# kept\r\n text  \r\n\nPackage: other\nVersion: 1.0\n"
+        );
+        paragraphs[1].set("version", "2.0");
+        paragraphs[1].set("SHA256", "\n 0123 42 Packages");
+        assert_eq!(
+            paragraphs[1].to_string(),
+            "Package: other\nVersion: 2.0\nSHA256:\n 0123 42 Packages\n"
+        );
+        Ok(())
+    }
+}
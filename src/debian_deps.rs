@@ -1,15 +1,31 @@
 use crate::debian_version::DebianVersion;
-use crate::index::{Dependency, Index};
+use crate::index::{
+    unqualified, Alternative, Dependency, Entry, HashedRange, Index, MultiArch, PackageName,
+    Priority,
+};
+use crate::status::InstalledSet;
 use core::fmt::Display;
-use pubgrub::{Dependencies, DependencyConstraints, DependencyProvider, Map, Range};
+use pubgrub::{
+    DefaultStringReporter, Dependencies, DependencyConstraints, DependencyProvider, DerivationTree,
+    Derived, External, Map, PubGrubError, Range, ReportFormatter, Reporter, SelectedDependencies,
+    Term,
+};
+use std::cell::RefCell;
 use std::convert::Infallible;
+use std::error::Error;
+use std::ops::Bound;
 use std::str::FromStr;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum Package {
+    /// The requested packages. A range containing [`not_installed`] other than the full
+    /// range also allows the package to be left off, see [`removed`] and [`if_installed`].
     Root(Vec<(Package, Range<DebianVersion>)>),
     Base(String),
     Proxy(Dependency),
+    /// A Recommends, which may be left unsatisfied by choosing [`not_installed`]. Also keeps
+    /// installed packages that a dist-upgrade may remove.
+    Optional(Dependency),
 }
 
 impl FromStr for Package {
@@ -29,25 +45,286 @@ impl Display for Package {
             Package::Root(_) => write!(f, "Root"),
             Package::Base(pkg) => write!(f, "{}", pkg),
             Package::Proxy(dependency) => write!(f, "{}", dependency),
+            Package::Optional(dependency) => write!(f, "optional {}", dependency),
+        }
+    }
+}
+
+/// Pseudo-version standing for a package that is not installed.
+///
+/// Real Debian versions are never empty, and packages with an empty Version are rejected
+/// when parsing, so this can not clash with an indexed version.
+/// It is chosen for packages kept off the system by Conflicts, Breaks or removals, and for
+/// [`Package::Optional`] packages left unsatisfied.
+pub fn not_installed() -> DebianVersion {
+    DebianVersion("".to_string())
+}
+
+/// Restrict a range to versions that are actually installed.
+pub fn installed(range: &Range<DebianVersion>) -> Range<DebianVersion> {
+    range.intersection(&Range::singleton(not_installed()).complement())
+}
+
+/// Root requirement range keeping a package off the system, as `apt-get remove`.
+pub fn removed() -> Range<DebianVersion> {
+    Range::singleton(not_installed())
+}
+
+/// Root requirement range restricting the version of a package only if it is installed,
+/// e.g. `if_installed(&Range::strictly_lower_than(v))` to keep it below `v`.
+pub fn if_installed(range: &Range<DebianVersion>) -> Range<DebianVersion> {
+    range.union(&removed())
+}
+
+/// Add a constraint, intersecting it with any constraint already present for the package.
+fn constrain(
+    map: &mut DependencyConstraints<Package, Range<DebianVersion>>,
+    package: Package,
+    range: Range<DebianVersion>,
+) {
+    match map.get_mut(&package) {
+        Some(existing) => *existing = existing.intersection(&range),
+        None => {
+            map.insert(package, range);
         }
     }
 }
 
 impl Index {
-    pub fn list_versions(&self, package: &Package) -> impl Iterator<Item = DebianVersion> + '_ {
+    /// Constraints for a list of dependencies declared by a package of architecture `arch`,
+    /// with virtual packages expanded to their providers.
+    pub fn from_dependencies(
+        &self,
+        dependencies: &[Dependency],
+        arch: &str,
+    ) -> DependencyConstraints<Package, Range<DebianVersion>> {
+        let mut map = Map::default();
+        for dependency in dependencies {
+            let dependency = self.resolve_dependency(dependency, arch);
+            match &dependency.alternatives[..] {
+                [dep] => constrain(
+                    &mut map,
+                    Package::Base(dep.name.clone()),
+                    installed(&dep.range.0),
+                ),
+                _ => constrain(&mut map, Package::Proxy(dependency), Range::full()),
+            };
+        }
+        map
+    }
+
+    /// Constraints keeping every package conflicting with or broken by `name` off the system.
+    ///
+    /// A conflict on a virtual package applies to all of its matching providers except
+    /// instances of `name` itself.
+    pub fn from_conflicts(
+        &self,
+        name: &str,
+        conflicts: &[Alternative],
+    ) -> DependencyConstraints<Package, Range<DebianVersion>> {
+        let mut map = Map::default();
+        for conflict in conflicts {
+            for resolved in self.resolve_conflict(conflict) {
+                if unqualified(&resolved.name) == unqualified(name) {
+                    continue;
+                }
+                let allowed = resolved
+                    .range
+                    .0
+                    .complement()
+                    .union(&Range::singleton(not_installed()));
+                constrain(&mut map, Package::Base(resolved.name), allowed);
+            }
+        }
+        map
+    }
+
+    /// Root package requiring the given packages together with every essential package and,
+    /// if a priority is given, every package of at least that priority.
+    ///
+    /// This reflects what actually lands on a Debian system, where those are always installed.
+    pub fn essential_root(
+        &self,
+        mut requirements: Vec<(Package, Range<DebianVersion>)>,
+        priority: Option<Priority>,
+    ) -> Package {
+        for name in self.essential_packages(priority) {
+            requirements.push((Package::Base(name), Range::full()));
+        }
+        Package::Root(requirements)
+    }
+
+    /// Constraints between the instances of a package for different architectures.
+    ///
+    /// Only `Multi-Arch: same` packages are co-installable, and then only at the same version.
+    pub fn from_multi_arch(
+        &self,
+        name: &str,
+        version: &DebianVersion,
+        entry: &Entry,
+    ) -> DependencyConstraints<Package, Range<DebianVersion>> {
+        let mut map = Map::default();
+        for arch in self.architectures() {
+            let other = self.qualify(unqualified(name), arch);
+            if other == name || !self.packages.contains_key(&other) {
+                continue;
+            }
+            let allowed = match entry.multi_arch {
+                MultiArch::Same => Range::singleton(version.clone()),
+                _ => Range::empty(),
+            };
+            constrain(
+                &mut map,
+                Package::Base(other),
+                allowed.union(&Range::singleton(not_installed())),
+            );
+        }
+        map
+    }
+
+    /// Installed packages whose dependencies are not met by the installed set or which
+    /// conflict with or break an installed package, as `apt-get check` reports them.
+    ///
+    /// Installed versions unknown to the index cannot be checked and are left out.
+    pub fn broken_packages(&self, installed: &InstalledSet) -> Vec<(PackageName, DebianVersion)> {
+        let is_installed = |alternative: &Alternative| {
+            installed
+                .version(&alternative.name)
+                .is_some_and(|version| alternative.range.0.contains(version))
+        };
+        let mut broken = Vec::new();
+        for package in installed.installed() {
+            let Some(entry) = self
+                .packages
+                .get(&package.name)
+                .and_then(|versions| versions.get(&package.version))
+            else {
+                continue;
+            };
+            let arch = &entry.architecture;
+            let unmet = entry.depends.iter().chain(&entry.pre_depends).any(|dep| {
+                !self
+                    .resolve_dependency(dep, arch)
+                    .alternatives
+                    .iter()
+                    .any(is_installed)
+            });
+            let conflicting = entry.conflicts.iter().chain(&entry.breaks).any(|conflict| {
+                self.resolve_conflict(conflict).iter().any(|resolved| {
+                    unqualified(&resolved.name) != unqualified(&package.name)
+                        && is_installed(resolved)
+                })
+            });
+            if unmet || conflicting {
+                broken.push((package.name.clone(), package.version.clone()));
+            }
+        }
+        broken
+    }
+}
+
+/// Options of a resolution that are not part of the index.
+#[derive(Debug, Clone, Default)]
+pub struct SolveOptions {
+    /// Whether Recommends are followed, see [`resolve_with_recommends`].
+    pub install_recommends: bool,
+    /// Version to try first for packages that should stay installed, which are only
+    /// removed as a last resort. See [`crate::upgrade`].
+    pub preferred: Map<PackageName, DebianVersion>,
+    /// Versions installed on the system being solved for, see [`Index::candidate_versions`].
+    pub installed: Map<PackageName, DebianVersion>,
+}
+
+/// Dependency provider resolving over an index with the given options.
+pub struct Solver<'a> {
+    pub index: &'a Index,
+    pub options: SolveOptions,
+    /// Candidate versions of the packages met so far. The index can not change while it is
+    /// borrowed, so they are computed once per package.
+    candidates: RefCell<Map<PackageName, Vec<DebianVersion>>>,
+}
+
+/// The versions a resolution selects, [`not_installed`] for packages left off the system.
+pub type Solution = SelectedDependencies<Solver<'static>>;
+
+impl<'a> From<&'a Index> for Solver<'a> {
+    /// Resolve with the default options.
+    fn from(index: &'a Index) -> Self {
+        Solver::new(index, SolveOptions::default())
+    }
+}
+
+impl<'a> Solver<'a> {
+    pub fn new(index: &'a Index, options: SolveOptions) -> Self {
+        Self {
+            index,
+            options,
+            candidates: RefCell::default(),
+        }
+    }
+
+    /// Resolve the dependencies of a package, explaining the failure if there is no
+    /// solution, see [`explain`].
+    pub fn resolve(
+        &self,
+        package: Package,
+        version: DebianVersion,
+    ) -> Result<Solution, Box<dyn Error>> {
+        match pubgrub::resolve(self, package, version) {
+            Ok(solution) => Ok(solution),
+            Err(PubGrubError::NoSolution(mut derivation_tree)) => {
+                Err(explain(self.index, &mut derivation_tree).into())
+            }
+            Err(err) => Err(err.to_string().into()),
+        }
+    }
+
+    /// Versions of a package in the order of [`Index::candidate_versions`] for the
+    /// installed system.
+    fn candidates(&self, name: &str) -> Vec<DebianVersion> {
+        self.candidates
+            .borrow_mut()
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                self.index
+                    .candidate_versions(name, self.options.installed.get(name))
+            })
+            .clone()
+    }
+
+    pub fn list_versions(&self, package: &Package) -> impl Iterator<Item = DebianVersion> {
         let versions = match package {
             Package::Root(_) => vec![DebianVersion("".to_string())],
-            Package::Base(pkg) => self.available_versions(pkg),
+            Package::Base(pkg) => match self.options.preferred.get(pkg) {
+                Some(preferred) => {
+                    let mut versions = self.candidates(pkg);
+                    if let Some(i) = versions.iter().position(|v| v == preferred) {
+                        let preferred = versions.remove(i);
+                        versions.insert(0, preferred);
+                    }
+                    versions.push(not_installed());
+                    versions
+                }
+                None => std::iter::once(not_installed())
+                    .chain(self.candidates(pkg))
+                    .collect(),
+            },
             Package::Proxy(dependencies) => dependencies
                 .clone()
                 .alternatives
                 .into_iter()
                 .map(|dep| DebianVersion(dep.name))
                 .collect(),
+            Package::Optional(dependency) => dependency
+                .alternatives
+                .iter()
+                .map(|dep| DebianVersion(dep.name.clone()))
+                .chain(std::iter::once(not_installed()))
+                .collect(),
         };
-        if self.version_debug.get() {
+        if self.index.version_debug.get() {
             print!("versions of {}", package);
-            if versions.len() > 0 {
+            if !versions.is_empty() {
                 print!(": ")
             }
             let mut first = true;
@@ -64,7 +341,7 @@ impl Index {
     }
 }
 
-impl DependencyProvider for Index {
+impl DependencyProvider for Solver<'_> {
     type P = Package;
 
     type V = DebianVersion;
@@ -77,13 +354,30 @@ impl DependencyProvider for Index {
 
     type Priority = u8;
 
+    /// Upgrades, i.e. packages preferred at another version than the installed one, are
+    /// decided first, so that they win over keeping the packages they break, see
+    /// `test_upgrade` in [`crate::upgrade`]. Conflict counts are not used, so that which
+    /// packages are kept does not depend on the course of the search.
     fn prioritize(
         &self,
-        _package: &Self::P,
+        package: &Self::P,
         _range: &Self::VS,
         _package_conflicts_counts: &pubgrub::PackageResolutionStatistics,
     ) -> Self::Priority {
-        1
+        let name = match package {
+            Package::Base(name) => name,
+            Package::Optional(dependency) if dependency.alternatives.len() == 1 => {
+                &dependency.alternatives[0].name
+            }
+            _ => return 1,
+        };
+        match (
+            self.options.preferred.get(name),
+            self.options.installed.get(name),
+        ) {
+            (Some(preferred), Some(installed)) if preferred != installed => 2,
+            _ => 1,
+        }
     }
 
     fn choose_version(
@@ -91,10 +385,7 @@ impl DependencyProvider for Index {
         package: &Self::P,
         range: &Self::VS,
     ) -> Result<Option<Self::V>, Self::Err> {
-        Ok(self
-            .list_versions(package)
-            .filter(|v| range.contains(v))
-            .next())
+        Ok(self.list_versions(package).find(|v| range.contains(v)))
     }
 
     fn get_dependencies(
@@ -103,20 +394,85 @@ impl DependencyProvider for Index {
         version: &DebianVersion,
     ) -> Result<Dependencies<Self::P, Self::VS, Self::M>, Self::Err> {
         match package {
-            Package::Root(deps) => Ok(Dependencies::Available(deps.into_iter().cloned().collect())),
+            Package::Root(deps) => {
+                let mut map = Map::default();
+                for (package, range) in deps {
+                    match package {
+                        Package::Base(name)
+                            if range.contains(&not_installed()) && *range != Range::full() =>
+                        {
+                            // A negative requirement is a conflict of the root, which also
+                            // applies to the providers of a virtual package. Removals are
+                            // unversioned conflicts, so that unversioned Provides match.
+                            let conflicting = if *range == removed() {
+                                Range::full()
+                            } else {
+                                range.complement()
+                            };
+                            let conflict = Alternative {
+                                name: unqualified(name).to_string(),
+                                range: HashedRange(conflicting),
+                                arch: name.split_once(':').map(|(_, arch)| arch.to_string()),
+                            };
+                            for (package, range) in self.index.from_conflicts("", &[conflict]) {
+                                constrain(&mut map, package, range);
+                            }
+                        }
+                        Package::Base(name) => {
+                            let arch = name.split_once(':').map(|(_, arch)| arch.to_string());
+                            let dependency = Dependency {
+                                alternatives: vec![Alternative {
+                                    name: unqualified(name).to_string(),
+                                    range: HashedRange(range.clone()),
+                                    arch,
+                                }],
+                            };
+                            let native = &self.index.native_arch;
+                            for (package, range) in
+                                self.index.from_dependencies(&[dependency], native)
+                            {
+                                constrain(&mut map, package, range);
+                            }
+                        }
+                        _ => constrain(&mut map, package.clone(), range.clone()),
+                    }
+                }
+                Ok(Dependencies::Available(map))
+            }
+            Package::Base(_) if *version == not_installed() => {
+                Ok(Dependencies::Available(Map::default()))
+            }
             Package::Base(pkg) => {
-                let all_versions = match self.packages.get(pkg) {
+                let all_versions = match self.index.packages.get(pkg) {
                     None => return Ok(Dependencies::Unavailable("".to_string())),
                     Some(all_versions) => all_versions,
                 };
-                let dependencies = match all_versions.get(version) {
+                let entry = match all_versions.get(version) {
                     None => return Ok(Dependencies::Unavailable("".to_string())),
-                    Some(d) => d,
+                    Some(e) => e,
                 };
-                let deps = from_dependencies(dependencies);
-                if self.debug.get() {
+                let arch = &entry.architecture;
+                let mut deps = self.index.from_dependencies(&entry.depends, arch);
+                for (package, range) in self.index.from_dependencies(&entry.pre_depends, arch) {
+                    constrain(&mut deps, package, range);
+                }
+                for conflicts in [&entry.conflicts, &entry.breaks] {
+                    for (package, range) in self.index.from_conflicts(pkg, conflicts) {
+                        constrain(&mut deps, package, range);
+                    }
+                }
+                for (package, range) in self.index.from_multi_arch(pkg, version, entry) {
+                    constrain(&mut deps, package, range);
+                }
+                if self.options.install_recommends {
+                    for recommends in &entry.recommends {
+                        let recommends = self.index.resolve_dependency(recommends, arch);
+                        constrain(&mut deps, Package::Optional(recommends), Range::full());
+                    }
+                }
+                if self.index.debug.get() {
                     print!("({}, {})", package, version);
-                    if deps.len() > 0 {
+                    if !deps.is_empty() {
                         print!(" -> ")
                     }
                     let mut first = true;
@@ -131,11 +487,11 @@ impl DependencyProvider for Index {
                 }
                 Ok(Dependencies::Available(deps))
             }
-            Package::Proxy(dependency) => {
+            Package::Proxy(dependency) | Package::Optional(dependency) => {
                 let deps = from_proxy(dependency, version);
-                if self.debug.get() {
+                if self.index.debug.get() {
                     print!("({}, {})", package, version);
-                    if deps.len() > 0 {
+                    if !deps.is_empty() {
                         print!(" -> ")
                     }
                     let mut first = true;
@@ -154,19 +510,6 @@ impl DependencyProvider for Index {
     }
 }
 
-pub fn from_dependencies(
-    dependencies: &Vec<Dependency>,
-) -> DependencyConstraints<Package, Range<DebianVersion>> {
-    let mut map = Map::default();
-    for dependency in dependencies.clone() {
-        match &dependency.alternatives[..] {
-            [dep] => map.insert(Package::Base(dep.name.clone()), dep.range.0.clone()),
-            _ => map.insert(Package::Proxy(dependency), Range::full()),
-        };
-    }
-    map
-}
-
 pub fn from_proxy(
     dependency: &Dependency,
     version: &DebianVersion,
@@ -175,10 +518,806 @@ pub fn from_proxy(
     for alt in dependency.alternatives.clone() {
         match &alt.name {
             n if version.to_string().eq(n) => {
-                map.insert(Package::Base(alt.name), alt.range.0.clone());
+                constrain(&mut map, Package::Base(alt.name), installed(&alt.range.0));
             }
             _ => {}
         }
     }
     map
 }
+
+/// The versions of a range as a Debian version relation such as `>= 2`, or `None` for any
+/// installed version. [`not_installed`] is left out.
+fn relation(range: &Range<DebianVersion>) -> Option<String> {
+    let range = installed(range);
+    if range == installed(&Range::full()) {
+        return None;
+    }
+    if range == Range::empty() {
+        return Some("not installed".to_string());
+    }
+    let segments: Vec<String> = range
+        .iter()
+        .map(|segment| match segment {
+            (Bound::Included(low), Bound::Included(high)) if low == high => format!("= {}", low),
+            (low, high) => {
+                let low = match low {
+                    Bound::Included(low) => Some(format!(">= {}", low)),
+                    Bound::Excluded(low) if *low != not_installed() => Some(format!(">> {}", low)),
+                    _ => None,
+                };
+                let high = match high {
+                    Bound::Included(high) => Some(format!("<= {}", high)),
+                    Bound::Excluded(high) => Some(format!("<< {}", high)),
+                    Bound::Unbounded => None,
+                };
+                low.into_iter().chain(high).collect::<Vec<_>>().join(", ")
+            }
+        })
+        .collect();
+    Some(segments.join(" | "))
+}
+
+/// A package with a range of its versions, in Debian terms for real packages.
+fn describe(package: &Package, range: &Range<DebianVersion>) -> String {
+    match package {
+        Package::Base(_) => match relation(range) {
+            Some(relation) => format!("{} ({})", package, relation),
+            None => package.to_string(),
+        },
+        Package::Root(_) => package.to_string(),
+        _ if *range == Range::full() => package.to_string(),
+        _ => format!("{} {}", package, range),
+    }
+}
+
+/// Formatter of the reports of [`explain`].
+///
+/// Ranges allowing a real package to stay off the system come from Conflicts, Breaks and
+/// removals, and are reported as conflicts with the versions they exclude.
+struct DebianReportFormatter;
+
+impl DebianReportFormatter {
+    /// A term on a real package which is also satisfied by leaving the package off the
+    /// system, stated as the versions it rules out instead.
+    fn normalize(
+        package: &Package,
+        term: &Term<Range<DebianVersion>>,
+    ) -> Term<Range<DebianVersion>> {
+        match (package, term) {
+            (Package::Base(_), Term::Positive(range))
+                if range.contains(&not_installed()) && *range != Range::full() =>
+            {
+                Term::Negative(range.complement())
+            }
+            _ => term.clone(),
+        }
+    }
+}
+
+impl ReportFormatter<Package, Range<DebianVersion>, String> for DebianReportFormatter {
+    type Output = String;
+
+    fn format_external(
+        &self,
+        external: &External<Package, Range<DebianVersion>, String>,
+    ) -> String {
+        match external {
+            External::NoVersions(package, range) if relation(range).is_none() => {
+                format!("there is no available version for {}", package)
+            }
+            External::NoVersions(package, range) => {
+                format!("there is no version of {}", describe(package, range))
+            }
+            External::FromDependencyOf(package, range, dependency, dependency_range)
+                if matches!(dependency, Package::Base(_))
+                    && dependency_range.contains(&not_installed())
+                    && *dependency_range != Range::full() =>
+            {
+                format!(
+                    "{} conflicts with {}",
+                    describe(package, range),
+                    describe(dependency, &dependency_range.complement())
+                )
+            }
+            External::FromDependencyOf(package, range, dependency, dependency_range) => {
+                format!(
+                    "{} depends on {}",
+                    describe(package, range),
+                    describe(dependency, dependency_range)
+                )
+            }
+            External::NotRoot(..) | External::Custom(..) => external.to_string(),
+        }
+    }
+
+    fn format_terms(&self, terms: &Map<Package, Term<Range<DebianVersion>>>) -> String {
+        let terms: Vec<_> = terms
+            .iter()
+            .map(|(package, term)| (package, Self::normalize(package, term)))
+            .collect();
+        match &terms[..] {
+            [] => "version solving failed".to_string(),
+            [(package, Term::Positive(range))] => {
+                format!("{} is forbidden", describe(package, range))
+            }
+            [(package, Term::Negative(range))] => {
+                format!("{} is mandatory", describe(package, range))
+            }
+            [(p1, Term::Positive(r1)), (p2, Term::Negative(r2))]
+            | [(p2, Term::Negative(r2)), (p1, Term::Positive(r1))] => self.format_external(
+                &External::FromDependencyOf((*p1).clone(), r1.clone(), (*p2).clone(), r2.clone()),
+            ),
+            terms => {
+                let terms: Vec<String> = terms
+                    .iter()
+                    .map(|(package, term)| match term {
+                        Term::Positive(range) => describe(package, range),
+                        Term::Negative(range) => format!("not {}", describe(package, range)),
+                    })
+                    .collect();
+                terms.join(", ") + " are incompatible"
+            }
+        }
+    }
+
+    fn explain_both_external(
+        &self,
+        external1: &External<Package, Range<DebianVersion>, String>,
+        external2: &External<Package, Range<DebianVersion>, String>,
+        current_terms: &Map<Package, Term<Range<DebianVersion>>>,
+    ) -> String {
+        format!(
+            "Because {} and {}, {}.",
+            self.format_external(external1),
+            self.format_external(external2),
+            self.format_terms(current_terms)
+        )
+    }
+
+    fn explain_both_ref(
+        &self,
+        ref_id1: usize,
+        derived1: &Derived<Package, Range<DebianVersion>, String>,
+        ref_id2: usize,
+        derived2: &Derived<Package, Range<DebianVersion>, String>,
+        current_terms: &Map<Package, Term<Range<DebianVersion>>>,
+    ) -> String {
+        format!(
+            "Because {} ({}) and {} ({}), {}.",
+            self.format_terms(&derived1.terms),
+            ref_id1,
+            self.format_terms(&derived2.terms),
+            ref_id2,
+            self.format_terms(current_terms)
+        )
+    }
+
+    fn explain_ref_and_external(
+        &self,
+        ref_id: usize,
+        derived: &Derived<Package, Range<DebianVersion>, String>,
+        external: &External<Package, Range<DebianVersion>, String>,
+        current_terms: &Map<Package, Term<Range<DebianVersion>>>,
+    ) -> String {
+        format!(
+            "Because {} ({}) and {}, {}.",
+            self.format_terms(&derived.terms),
+            ref_id,
+            self.format_external(external),
+            self.format_terms(current_terms)
+        )
+    }
+
+    fn and_explain_external(
+        &self,
+        external: &External<Package, Range<DebianVersion>, String>,
+        current_terms: &Map<Package, Term<Range<DebianVersion>>>,
+    ) -> String {
+        format!(
+            "And because {}, {}.",
+            self.format_external(external),
+            self.format_terms(current_terms)
+        )
+    }
+
+    fn and_explain_ref(
+        &self,
+        ref_id: usize,
+        derived: &Derived<Package, Range<DebianVersion>, String>,
+        current_terms: &Map<Package, Term<Range<DebianVersion>>>,
+    ) -> String {
+        format!(
+            "And because {} ({}), {}.",
+            self.format_terms(&derived.terms),
+            ref_id,
+            self.format_terms(current_terms)
+        )
+    }
+
+    fn and_explain_prior_and_external(
+        &self,
+        prior_external: &External<Package, Range<DebianVersion>, String>,
+        external: &External<Package, Range<DebianVersion>, String>,
+        current_terms: &Map<Package, Term<Range<DebianVersion>>>,
+    ) -> String {
+        format!(
+            "And because {} and {}, {}.",
+            self.format_external(prior_external),
+            self.format_external(external),
+            self.format_terms(current_terms)
+        )
+    }
+}
+
+/// Add the ranges of the packages of the external incompatibilities of a derivation tree
+/// to `ranges`, those of the same package being merged.
+fn external_ranges(
+    derivation_tree: &DerivationTree<Package, Range<DebianVersion>, String>,
+    ranges: &mut Map<Package, Range<DebianVersion>>,
+) {
+    let mut add = |package: &Package, range: &Range<DebianVersion>| {
+        let merged = match ranges.get(package) {
+            Some(known) => known.union(range),
+            None => range.clone(),
+        };
+        ranges.insert(package.clone(), merged);
+    };
+    match derivation_tree {
+        DerivationTree::External(External::NotRoot(package, version)) => {
+            add(package, &Range::singleton(version.clone()))
+        }
+        DerivationTree::External(
+            External::NoVersions(package, range) | External::Custom(package, range, _),
+        ) => add(package, range),
+        DerivationTree::External(External::FromDependencyOf(
+            package,
+            range,
+            dependency,
+            dependency_range,
+        )) => {
+            add(package, range);
+            add(dependency, dependency_range);
+        }
+        DerivationTree::Derived(derived) => {
+            external_ranges(&derived.cause1, ranges);
+            external_ranges(&derived.cause2, ranges);
+        }
+    }
+}
+
+/// Explain why there is no solution, listing the repositories the versions of the packages
+/// involved come from when they are known.
+///
+/// Only the versions within the ranges the report is about are listed.
+pub fn explain(
+    index: &Index,
+    derivation_tree: &mut DerivationTree<Package, Range<DebianVersion>, String>,
+) -> String {
+    derivation_tree.collapse_no_versions();
+    let mut report =
+        DefaultStringReporter::report_with_formatter(derivation_tree, &DebianReportFormatter);
+    let mut ranges = Map::default();
+    external_ranges(derivation_tree, &mut ranges);
+    let mut names: Vec<(&String, &Range<DebianVersion>)> = ranges
+        .iter()
+        .filter_map(|(package, range)| match package {
+            Package::Base(name) => Some((name, range)),
+            _ => None,
+        })
+        .collect();
+    names.sort();
+    for (name, range) in names {
+        for (version, entry) in index.packages.get(name).into_iter().flatten().rev() {
+            if entry.origins.is_empty() || !range.contains(version) {
+                continue;
+            }
+            let origins: Vec<String> = entry.origins.iter().map(|o| o.to_string()).collect();
+            report.push_str(&format!(
+                "\n{} {} is from {}",
+                name,
+                version,
+                origins.join(", ")
+            ));
+        }
+    }
+    report
+}
+
+/// A Recommends left unsatisfied by [`resolve_with_recommends`].
+#[derive(Debug, Clone)]
+pub struct DroppedRecommends {
+    pub package: String,
+    pub version: DebianVersion,
+    pub recommends: Dependency,
+    /// Explanation of why the recommendation could not be satisfied.
+    pub reason: String,
+}
+
+/// Resolve like apt with `APT::Install-Recommends`: Recommends are satisfied when possible,
+/// and those that are not are reported along with the reason, instead of failing the resolution.
+pub fn resolve_with_recommends(
+    index: &Index,
+    package: Package,
+    version: DebianVersion,
+) -> Result<(Solution, Vec<DroppedRecommends>), Box<dyn Error>> {
+    let options = SolveOptions {
+        install_recommends: true,
+        ..SolveOptions::default()
+    };
+    let solver = Solver::new(index, options);
+    let sol = solver.resolve(package.clone(), version.clone())?;
+    let mut dropped = Vec::new();
+    for (name, selected) in &sol {
+        let Package::Base(name) = name else {
+            continue;
+        };
+        let Some(entry) = index.packages.get(name).and_then(|v| v.get(selected)) else {
+            continue;
+        };
+        for recommends in &entry.recommends {
+            let resolved = index.resolve_dependency(recommends, &entry.architecture);
+            if sol.get(&Package::Optional(resolved.clone())) != Some(&not_installed()) {
+                continue;
+            }
+            // Explain the failure by requiring the recommendation on top of the request.
+            let requirement = match &resolved.alternatives[..] {
+                [alt] => (Package::Base(alt.name.clone()), alt.range.0.clone()),
+                _ => (Package::Proxy(resolved.clone()), Range::full()),
+            };
+            let forced = Package::Root(vec![
+                (package.clone(), Range::singleton(version.clone())),
+                (
+                    Package::Base(name.clone()),
+                    Range::singleton(selected.clone()),
+                ),
+                requirement,
+            ]);
+            let reason = match solver.resolve(forced, not_installed()) {
+                Err(err) => err.to_string(),
+                Ok(_) => "not selected in favour of other choices".to_string(),
+            };
+            dropped.push(DroppedRecommends {
+                package: name.clone(),
+                version: selected.clone(),
+                recommends: recommends.clone(),
+                reason,
+            });
+        }
+    }
+    Ok((sol, dropped))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::{Flag, InstalledPackage, State, Status, Want};
+
+    fn alt(name: &str, range: Range<DebianVersion>) -> Alternative {
+        Alternative {
+            name: name.to_string(),
+            range: HashedRange(range),
+            arch: None,
+        }
+    }
+
+    fn dep(alternatives: Vec<Alternative>) -> Dependency {
+        Dependency { alternatives }
+    }
+
+    fn v(version: &str) -> DebianVersion {
+        DebianVersion(version.to_string())
+    }
+
+    fn resolve(index: &Index, deps: Vec<(&str, Range<DebianVersion>)>) -> Option<Vec<String>> {
+        let root = Package::Root(
+            deps.into_iter()
+                .map(|(name, range)| (Package::Base(name.to_string()), range))
+                .collect(),
+        );
+        let sol = Solver::from(index).resolve(root, v("")).ok()?;
+        let mut installed: Vec<String> = sol
+            .into_iter()
+            .filter_map(|(package, version)| match package {
+                Package::Base(name) if version != not_installed() => {
+                    Some(format!("{}={}", name, version))
+                }
+                _ => None,
+            })
+            .collect();
+        installed.sort();
+        Some(installed)
+    }
+
+    #[test]
+    fn test_conflicting_mtas() {
+        let mut index = Index::new();
+        for mta in ["exim4", "postfix"] {
+            index.add_deps(mta, v("1"), vec![]);
+            index.add_conflicts(
+                mta,
+                v("1"),
+                vec![alt("mail-transport-agent", Range::full())],
+                vec![],
+            );
+            index.add_provider("mail-transport-agent", mta, v("1"), None);
+        }
+        assert_eq!(
+            resolve(&index, vec![("exim4", Range::full())]),
+            Some(vec!["exim4=1".to_string()])
+        );
+        assert_eq!(
+            resolve(
+                &index,
+                vec![("exim4", Range::full()), ("postfix", Range::full())]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_negative_requests() {
+        let mut index = Index::new();
+        index.add_deps("lib", v("1"), vec![]);
+        index.add_deps("lib", v("2"), vec![]);
+        index.add_deps("app", v("1"), vec![dep(vec![alt("lib", Range::full())])]);
+        for mta in ["exim4", "postfix"] {
+            index.add_deps(mta, v("1"), vec![]);
+            index.add_provider("mail-transport-agent", mta, v("1"), None);
+        }
+        index.add_deps(
+            "mailutils",
+            v("1"),
+            vec![dep(vec![alt("mail-transport-agent", Range::full())])],
+        );
+
+        assert_eq!(
+            resolve(&index, vec![("app", Range::full()), ("lib", removed())]),
+            None
+        );
+        assert_eq!(
+            resolve(
+                &index,
+                vec![
+                    ("app", Range::full()),
+                    ("lib", if_installed(&Range::strictly_lower_than(v("2"))))
+                ]
+            ),
+            Some(vec!["app=1".to_string(), "lib=1".to_string()])
+        );
+        assert_eq!(
+            resolve(&index, vec![("lib", if_installed(&Range::full()))]),
+            Some(vec!["lib=2".to_string()])
+        );
+        assert_eq!(resolve(&index, vec![("lib", removed())]), Some(vec![]));
+        assert_eq!(
+            resolve(
+                &index,
+                vec![("mailutils", Range::full()), ("exim4", removed())]
+            ),
+            Some(vec!["mailutils=1".to_string(), "postfix=1".to_string()])
+        );
+        assert_eq!(
+            resolve(
+                &index,
+                vec![
+                    ("mailutils", Range::full()),
+                    ("mail-transport-agent", removed())
+                ]
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_versioned_breaks() {
+        let mut index = Index::new();
+        index.add_deps("libfoo", v("2"), vec![]);
+        index.add_conflicts(
+            "libfoo",
+            v("2"),
+            vec![],
+            vec![alt("bar", Range::strictly_lower_than(v("2")))],
+        );
+        index.add_deps("bar", v("1"), vec![]);
+        index.add_deps("bar", v("2"), vec![]);
+        index.add_deps(
+            "app",
+            v("1"),
+            vec![
+                dep(vec![alt("libfoo", Range::full())]),
+                dep(vec![alt("bar", Range::full())]),
+            ],
+        );
+        assert_eq!(
+            resolve(&index, vec![("app", Range::full())]),
+            Some(vec![
+                "app=1".to_string(),
+                "bar=2".to_string(),
+                "libfoo=2".to_string()
+            ])
+        );
+        assert_eq!(
+            resolve(
+                &index,
+                vec![("libfoo", Range::full()), ("bar", Range::singleton(v("1")))]
+            ),
+            None
+        );
+        // A package that is only broken, never required, stays off the system.
+        assert_eq!(
+            resolve(&index, vec![("libfoo", Range::full())]),
+            Some(vec!["libfoo=2".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_versioned_provides() {
+        let mut index = Index::new();
+        index.add_deps("foo-impl", v("3.0-1"), vec![]);
+        index.add_provider("foo", "foo-impl", v("3.0-1"), Some(v("2.1")));
+        index.add_deps("foo-compat", v("1.0"), vec![]);
+        index.add_provider("foo", "foo-compat", v("1.0"), None);
+        assert_eq!(
+            resolve(&index, vec![("foo", Range::higher_than(v("2")))]),
+            Some(vec!["foo-impl=3.0-1".to_string()])
+        );
+        // Unversioned provides never satisfy a versioned dependency.
+        assert_eq!(
+            resolve(&index, vec![("foo", Range::higher_than(v("3")))]),
+            None
+        );
+        index.add_deps("app", v("1"), vec![dep(vec![alt("foo", Range::full())])]);
+        index.add_conflicts(
+            "foo-compat",
+            v("1.0"),
+            vec![alt("foo", Range::full())],
+            vec![],
+        );
+        assert_eq!(
+            resolve(
+                &index,
+                vec![("app", Range::full()), ("foo-compat", Range::full())]
+            ),
+            Some(vec!["app=1".to_string(), "foo-compat=1.0".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_pre_depends() {
+        let mut index = Index::new();
+        index.add_deps("dpkg", v("1.19.7"), vec![]);
+        index.add_deps("libc6", v("2.28-10"), vec![]);
+        index.add_deps("app", v("1"), vec![dep(vec![alt("libc6", Range::full())])]);
+        index.add_pre_deps("app", v("1"), vec![dep(vec![alt("dpkg", Range::full())])]);
+        assert_eq!(
+            resolve(&index, vec![("app", Range::full())]),
+            Some(vec![
+                "app=1".to_string(),
+                "dpkg=1.19.7".to_string(),
+                "libc6=2.28-10".to_string()
+            ])
+        );
+        let entry = &index.packages["app"][&v("1")];
+        assert_eq!(entry.depends.len(), 1);
+        assert_eq!(entry.pre_depends[0].alternatives[0].name, "dpkg");
+    }
+
+    #[test]
+    fn test_multi_arch() {
+        let mut index = Index::new();
+        index.set_architectures("amd64", &["i386"]);
+        for arch in ["amd64", "i386"] {
+            let libc6 = index.qualify("libc6", arch);
+            index.add_deps(&libc6, v("2.28-10"), vec![]);
+            index.add_arch(&libc6, v("2.28-10"), arch, MultiArch::Same);
+            let bash = index.qualify("bash", arch);
+            index.add_deps(&bash, v("5.0-4"), vec![]);
+            index.add_arch(&bash, v("5.0-4"), arch, MultiArch::No);
+        }
+        index.add_deps("python3", v("3.7.3-1"), vec![]);
+        index.add_arch("python3", v("3.7.3-1"), "amd64", MultiArch::Allowed);
+        index.add_deps("perl-base", v("5.28.1-6"), vec![]);
+        index.add_arch("perl-base", v("5.28.1-6"), "all", MultiArch::No);
+        let mut python3_any = alt("python3", Range::full());
+        python3_any.arch = Some("any".to_string());
+        index.add_deps(
+            "app:i386",
+            v("1"),
+            vec![
+                dep(vec![alt("libc6", Range::full())]),
+                dep(vec![python3_any]),
+            ],
+        );
+        index.add_arch("app:i386", v("1"), "i386", MultiArch::No);
+        assert_eq!(
+            resolve(
+                &index,
+                vec![("app:i386", Range::full()), ("libc6", Range::full())]
+            ),
+            Some(vec![
+                "app:i386=1".to_string(),
+                "libc6:i386=2.28-10".to_string(),
+                "libc6=2.28-10".to_string(),
+                "python3=3.7.3-1".to_string()
+            ])
+        );
+        // Only Multi-Arch: same packages are co-installable across architectures.
+        assert_eq!(
+            resolve(
+                &index,
+                vec![("bash", Range::full()), ("bash:i386", Range::full())]
+            ),
+            None
+        );
+        // Architecture: all counts as native unless marked Multi-Arch: foreign.
+        index.add_deps(
+            "tool:i386",
+            v("1"),
+            vec![dep(vec![alt("perl-base", Range::full())])],
+        );
+        index.add_arch("tool:i386", v("1"), "i386", MultiArch::No);
+        assert_eq!(resolve(&index, vec![("tool:i386", Range::full())]), None);
+        index.add_arch("perl-base", v("5.28.1-6"), "all", MultiArch::Foreign);
+        assert_eq!(
+            resolve(&index, vec![("tool:i386", Range::full())]),
+            Some(vec![
+                "perl-base=5.28.1-6".to_string(),
+                "tool:i386=1".to_string()
+            ])
+        );
+    }
+
+    #[test]
+    fn test_recommends() -> Result<(), Box<dyn Error>> {
+        let mut index = Index::new();
+        index.add_deps("openssh-server", v("1:7.9p1-10"), vec![]);
+        index.add_soft_deps(
+            "openssh-server",
+            v("1:7.9p1-10"),
+            vec![
+                dep(vec![alt("xauth", Range::full())]),
+                dep(vec![alt("ncurses-term", Range::full())]),
+                dep(vec![alt("molly-guard", Range::full())]),
+            ],
+            vec![dep(vec![alt("ufw", Range::full())])],
+            vec![],
+        );
+        index.add_deps("xauth", v("1:1.0.10-1"), vec![]);
+        index.add_deps("ncurses-term", v("6.1"), vec![]);
+        index.add_conflicts(
+            "ncurses-term",
+            v("6.1"),
+            vec![alt("openssh-server", Range::full())],
+            vec![],
+        );
+        index.add_deps("ufw", v("0.36-1"), vec![]);
+
+        // Recommends are ignored unless asked for.
+        assert_eq!(
+            resolve(&index, vec![("openssh-server", Range::full())]),
+            Some(vec!["openssh-server=1:7.9p1-10".to_string()])
+        );
+
+        let root = Package::Root(vec![(
+            Package::Base("openssh-server".to_string()),
+            Range::full(),
+        )]);
+        let (sol, dropped) = resolve_with_recommends(&index, root, v(""))?;
+        assert_eq!(
+            sol.get(&Package::Base("xauth".to_string())),
+            Some(&v("1:1.0.10-1"))
+        );
+        assert!(!sol.contains_key(&Package::Base("ufw".to_string())));
+        let mut dropped: Vec<String> = dropped.iter().map(|d| d.recommends.to_string()).collect();
+        dropped.sort();
+        assert_eq!(dropped, vec!["molly-guard: *", "ncurses-term: *"]);
+        assert_eq!(
+            resolve(&index, vec![("openssh-server", Range::full())]),
+            Some(vec!["openssh-server=1:7.9p1-10".to_string()])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_conflicts() {
+        let mut index = Index::new();
+        index.add_deps("lib", v("1"), vec![]);
+        index.add_deps("lib", v("2"), vec![]);
+        index.add_deps("old", v("1"), vec![]);
+        index.add_conflicts(
+            "old",
+            v("1"),
+            vec![alt("lib", Range::higher_than(v("2")))],
+            vec![],
+        );
+        index.add_deps(
+            "tool",
+            v("1"),
+            vec![dep(vec![alt("lib", Range::higher_than(v("2")))])],
+        );
+        let root = Package::Root(vec![
+            (Package::Base("old".to_string()), Range::full()),
+            (Package::Base("tool".to_string()), Range::full()),
+        ]);
+        let Err(PubGrubError::NoSolution(mut derivation_tree)) =
+            pubgrub::resolve(&Solver::from(&index), root, v(""))
+        else {
+            panic!("old and tool should not be co-installable");
+        };
+        let report = explain(&index, &mut derivation_tree);
+        assert!(report.contains("old conflicts with lib (>= 2)"));
+        assert!(report.contains("tool (= 1) depends on lib (>= 2)"));
+        assert!(!report.contains("< | >"));
+    }
+
+    #[test]
+    fn test_essential_root() {
+        let mut index = Index::new();
+        index.add_deps("dpkg", v("1.19.7"), vec![]);
+        index.add_essential("dpkg", v("1.19.7"), true, Some(Priority::Required));
+        index.add_deps("libc6", v("2.28-10"), vec![]);
+        index.add_essential("libc6", v("2.28-10"), false, Some(Priority::Required));
+        index.add_deps("apt", v("1.8.2"), vec![]);
+        index.add_essential("apt", v("1.8.2"), false, Some(Priority::Important));
+        index.add_deps("hello", v("2.10-2"), vec![]);
+        index.add_essential("hello", v("2.10-2"), false, Some(Priority::Optional));
+
+        let hello = vec![(Package::Base("hello".to_string()), Range::full())];
+        let root = index.essential_root(hello.clone(), None);
+        let sol = Solver::from(&index).resolve(root, v("")).unwrap();
+        assert!(sol.contains_key(&Package::Base("dpkg".to_string())));
+        assert!(!sol.contains_key(&Package::Base("libc6".to_string())));
+
+        let root = index.essential_root(hello, Some(Priority::Important));
+        let sol = Solver::from(&index).resolve(root, v("")).unwrap();
+        assert!(sol.contains_key(&Package::Base("libc6".to_string())));
+        assert!(sol.contains_key(&Package::Base("apt".to_string())));
+    }
+
+    #[test]
+    fn test_broken_packages() {
+        let mut index = Index::new();
+        index.add_deps(
+            "a",
+            v("1"),
+            vec![dep(vec![alt("b", Range::higher_than(v("2")))])],
+        );
+        index.add_deps("b", v("1"), vec![]);
+        index.add_deps("b", v("2"), vec![]);
+        index.add_deps("c", v("1"), vec![]);
+        index.add_conflicts("c", v("1"), vec![alt("b", Range::full())], vec![]);
+        index.add_deps("d", v("1"), vec![dep(vec![alt("e", Range::full())])]);
+        index.add_provider("e", "b", v("2"), None);
+
+        let status = Status {
+            want: Want::Install,
+            flag: Flag::Ok,
+            state: State::Installed,
+        };
+        let mut installed = InstalledSet::new();
+        for (name, version) in [("a", "1"), ("b", "1"), ("d", "1")] {
+            installed.insert(InstalledPackage {
+                name: name.to_string(),
+                version: v(version),
+                status,
+            });
+        }
+        assert_eq!(
+            index.broken_packages(&installed),
+            vec![("a".to_string(), v("1")), ("d".to_string(), v("1"))]
+        );
+
+        for (name, version) in [("b", "2"), ("c", "1")] {
+            installed.insert(InstalledPackage {
+                name: name.to_string(),
+                version: v(version),
+                status,
+            });
+        }
+        assert_eq!(
+            index.broken_packages(&installed),
+            vec![("c".to_string(), v("1"))]
+        );
+    }
+}
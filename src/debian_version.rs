@@ -31,6 +31,28 @@ impl DebianVersion {
         (epoch, upstream.to_string(), debian.to_string())
     }
 
+    /// Whether the version follows the syntax of deb-version(7): an optional numeric
+    /// epoch, a non-empty upstream version and, after the last hyphen, a non-empty Debian
+    /// revision, made of alphanumerics and `.+~`, plus `-` in the upstream version.
+    pub fn is_valid(&self) -> bool {
+        let (epoch, rest) = match self.0.split_once(':') {
+            Some((epoch, rest)) => (Some(epoch), rest),
+            None => (None, self.0.as_str()),
+        };
+        if epoch.is_some_and(|epoch| epoch.is_empty() || !epoch.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return false;
+        }
+        let (upstream, revision) = match rest.rsplit_once('-') {
+            Some((upstream, revision)) => (upstream, Some(revision)),
+            None => (rest, None),
+        };
+        let allowed = |c: char| c.is_ascii_alphanumeric() || ".+~".contains(c);
+        !upstream.is_empty()
+            && upstream.chars().all(|c| allowed(c) || c == '-')
+            && revision.is_none_or(|revision| !revision.is_empty() && revision.chars().all(allowed))
+    }
+
     /// Tokenizes a version component (either upstream or debian) into alternating
     /// non-digit and digit tokens.
     fn tokenize_str(s: &str) -> Vec<Token> {
@@ -61,7 +83,7 @@ fn tokenize(version: &str) -> Vec<Token> {
         }
     }
 
-    while let Some(ch) = chars.next() {
+    for ch in chars {
         let ch_is_digit = ch.is_ascii_digit();
         match is_digit {
             Some(current_is_digit) if current_is_digit == ch_is_digit => {
@@ -301,6 +323,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_is_valid() {
+        for version in [
+            "1",
+            "1:7.9p1-10+deb10u2",
+            "2.10-3+ci1",
+            "1.0~rc1-1-2",
+            "0:1.0",
+        ] {
+            assert!(DebianVersion(version.to_string()).is_valid(), "{}", version);
+        }
+        for version in ["", "1:", ":1", "a:1", "1.0-", "1.0 1", "1_0", "1:2:3"] {
+            assert!(
+                !DebianVersion(version.to_string()).is_valid(),
+                "{}",
+                version
+            );
+        }
+    }
+
     #[test]
     fn test_ordering() {
         // Example ordering from the documentation:
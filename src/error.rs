@@ -0,0 +1,186 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// What went wrong while parsing a control file.
+#[derive(Debug)]
+pub enum ParseErrorKind {
+    Io(io::Error),
+    InvalidUtf8,
+    /// A line that is neither a field, a continuation line nor a comment.
+    LineWithoutColon(String),
+    ContinuationWithoutField(String),
+    MissingField(&'static str),
+    /// A malformed item of a relationship field such as Depends.
+    InvalidRelation {
+        item: String,
+        reason: Box<dyn Error>,
+    },
+    InvalidValue(String),
+    /// A Provides item with alternatives, which dpkg does not allow.
+    ProvidesAlternatives(String),
+    /// A file whose size or checksum differs from the one listed in the Release file.
+    ChecksumMismatch(String),
+    /// A Release file past its Valid-Until date.
+    Expired(String),
+    /// A missing or invalid OpenPGP signature of a Release file.
+    BadSignature(String),
+}
+
+/// An error found while parsing a control file, with as much of its location as is known.
+///
+/// Stanzas are counted from 0, lines and columns from 1, with 0 for an unknown line
+/// or column.
+#[derive(Debug)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    pub path: Option<PathBuf>,
+    pub stanza: Option<usize>,
+    pub field: Option<String>,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl ParseError {
+    pub fn new(kind: ParseErrorKind) -> Self {
+        ParseError {
+            kind,
+            path: None,
+            stanza: None,
+            field: None,
+            line: 0,
+            column: 0,
+        }
+    }
+
+    pub fn in_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.path.get_or_insert(path.into());
+        self
+    }
+
+    pub fn in_stanza(mut self, stanza: usize) -> Self {
+        self.stanza.get_or_insert(stanza);
+        self
+    }
+
+    pub fn in_field(mut self, field: &str) -> Self {
+        self.field.get_or_insert(field.to_string());
+        self
+    }
+
+    pub fn at(mut self, line: usize, column: usize) -> Self {
+        if self.line == 0 {
+            self.line = line;
+            self.column = column;
+        }
+        self
+    }
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseErrorKind::Io(e) => write!(f, "{}", e),
+            ParseErrorKind::InvalidUtf8 => write!(f, "Invalid UTF-8"),
+            ParseErrorKind::LineWithoutColon(line) => write!(f, "Line without colon: {}", line),
+            ParseErrorKind::ContinuationWithoutField(line) => {
+                write!(f, "Continuation line without a field: {}", line)
+            }
+            ParseErrorKind::MissingField(name) => write!(f, "Missing {} field", name),
+            ParseErrorKind::InvalidRelation { item, reason } => {
+                write!(f, "Error parsing dependency '{}': {}", item, reason)
+            }
+            ParseErrorKind::InvalidValue(value) => write!(f, "Invalid value: {}", value),
+            ParseErrorKind::ProvidesAlternatives(item) => {
+                write!(f, "Alternatives are not allowed in Provides: {}", item)
+            }
+            ParseErrorKind::ChecksumMismatch(mismatch) => {
+                write!(f, "Checksum mismatch: {}", mismatch)
+            }
+            ParseErrorKind::Expired(date) => write!(f, "Release file expired on {}", date),
+            ParseErrorKind::BadSignature(reason) => {
+                write!(f, "Signature verification failed: {}", reason)
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    /// E.g. `Packages:12:9: stanza 3: Depends: Error parsing dependency ...`.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(path) = &self.path {
+            write!(f, "{}:", path.display())?;
+        }
+        if self.line != 0 {
+            write!(f, "{}:", self.line)?;
+        }
+        if self.column != 0 {
+            write!(f, "{}:", self.column)?;
+        }
+        if self.path.is_some() || self.line != 0 {
+            write!(f, " ")?;
+        }
+        if let Some(stanza) = self.stanza {
+            write!(f, "stanza {}: ", stanza)?;
+        }
+        if let Some(field) = &self.field {
+            write!(f, "{}: ", field)?;
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl Error for ParseError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match &self.kind {
+            ParseErrorKind::Io(e) => Some(e),
+            ParseErrorKind::InvalidRelation { reason, .. } => Some(reason.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ParseError {
+    fn from(e: io::Error) -> Self {
+        ParseError::new(ParseErrorKind::Io(e))
+    }
+}
+
+/// How parse errors are handled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Fail on the first error.
+    #[default]
+    Strict,
+    /// Skip what could not be parsed, i.e. the malformed relationship item or stanza,
+    /// and collect the errors as warnings.
+    Lenient,
+}
+
+/// Errors reported while parsing, according to the [`Mode`].
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    pub mode: Mode,
+    pub warnings: Vec<ParseError>,
+}
+
+impl Diagnostics {
+    pub fn new(mode: Mode) -> Self {
+        Diagnostics {
+            mode,
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Fail with an error in strict mode, or record it as a warning in lenient mode.
+    pub fn report(&mut self, error: ParseError) -> Result<(), ParseError> {
+        match self.mode {
+            Mode::Strict => Err(error),
+            Mode::Lenient => {
+                self.warnings.push(error);
+                Ok(())
+            }
+        }
+    }
+}
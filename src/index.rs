@@ -2,14 +2,29 @@ use core::fmt::Display;
 use pubgrub::{Map, Range};
 use std::cell::Cell;
 use std::collections::BTreeMap;
+use std::fs;
 use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use crate::debian_version::DebianVersion;
+use crate::preferences::Preferences;
+use crate::release::parse_release;
 
 pub type PackageName = String;
 
+/// Index of package instances keyed by (name, architecture).
+///
+/// The key is the architecture-qualified name, as apt prints it: packages of the native
+/// architecture or `Architecture: all` use their plain name, foreign ones `name:arch`.
 pub struct Index {
-    pub packages: Map<PackageName, BTreeMap<DebianVersion, Vec<Dependency>>>,
+    pub packages: Map<PackageName, BTreeMap<DebianVersion, Entry>>,
+    /// Concrete packages providing each virtual package name.
+    pub providers: Map<PackageName, Vec<Provider>>,
+    pub native_arch: String,
+    pub foreign_archs: Vec<String>,
+    /// Pins deciding which versions are preferred, see [`Index::candidate_versions`].
+    pub preferences: Preferences,
     pub debug: Cell<bool>,
     pub version_debug: Cell<bool>,
 }
@@ -31,6 +46,182 @@ impl Display for HashedRange {
     }
 }
 
+/// Relationships of a single version of a package.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Entry {
+    pub depends: Vec<Dependency>,
+    /// Dependencies that must be configured before this package is unpacked.
+    pub pre_depends: Vec<Dependency>,
+    pub recommends: Vec<Dependency>,
+    pub suggests: Vec<Dependency>,
+    pub enhances: Vec<Dependency>,
+    pub conflicts: Vec<Alternative>,
+    pub breaks: Vec<Alternative>,
+    /// Virtual packages the version provides, as keys of [`Index::providers`].
+    pub provides: Vec<PackageName>,
+    /// Value of the Architecture field, empty if unknown (treated as native).
+    pub architecture: String,
+    pub multi_arch: MultiArch,
+    /// Whether the package is marked `Essential: yes`.
+    pub essential: bool,
+    pub priority: Option<Priority>,
+    /// Repositories the version is available from, in the order they were loaded.
+    pub origins: Vec<Origin>,
+}
+
+/// Where a package version comes from, as described by the Release file of a repository.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct Origin {
+    /// Who makes the archive, e.g. `Debian`.
+    pub origin: String,
+    /// Archive label, e.g. `Debian-Security`.
+    pub label: String,
+    /// Suite or codename, e.g. `bookworm-security`.
+    pub suite: String,
+    pub codename: String,
+    /// Release version, e.g. `12.5`.
+    pub version: String,
+    pub component: String,
+    /// Host name the repository is downloaded from, e.g. `deb.debian.org`.
+    pub site: String,
+    /// `NotAutomatic: yes`, as for experimental and backports, whose packages are only
+    /// installed on request.
+    pub not_automatic: bool,
+    /// `ButAutomaticUpgrades: yes`, as for backports, whose installed packages are upgraded.
+    pub but_automatic_upgrades: bool,
+}
+
+impl Origin {
+    /// Origin of packages read from local `.deb` files, whose origin and suite are `local`.
+    pub fn local() -> Origin {
+        Origin {
+            origin: "local".to_string(),
+            suite: "local".to_string(),
+            ..Origin::default()
+        }
+    }
+
+    /// Origin of a Packages file of a mirror, as in
+    /// `dists/bookworm-security/main/binary-amd64/Packages`.
+    ///
+    /// The archive, its label and such are read from the `InRelease` or `Release` file of
+    /// the suite if there is one, without checking it. Files outside of a `dists` tree only
+    /// get their directory as suite.
+    pub fn from_path(path: &Path) -> Origin {
+        let parts: Vec<&str> = path
+            .iter()
+            .map(|part| part.to_str().unwrap_or_default())
+            .collect();
+        match parts.iter().rposition(|part| *part == "dists") {
+            Some(i) if i + 3 < parts.len() => {
+                let dist: PathBuf = path.iter().take(i + 2).collect();
+                let release = ["InRelease", "Release"]
+                    .iter()
+                    .find_map(|name| fs::read_to_string(dist.join(name)).ok())
+                    .and_then(|content| parse_release(&content).ok())
+                    .unwrap_or_default();
+                let mut origin = release.origin(&parts[i + 2..parts.len() - 2].join("/"));
+                if origin.suite.is_empty() {
+                    origin.suite = parts[i + 1].to_string();
+                }
+                origin
+            }
+            _ => Origin {
+                suite: path
+                    .parent()
+                    .map(|dir| dir.display().to_string())
+                    .unwrap_or_default(),
+                ..Origin::default()
+            },
+        }
+    }
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.label.is_empty() {
+            write!(f, "{} ", self.label)?;
+        }
+        write!(f, "{}", self.suite)?;
+        if !self.component.is_empty() {
+            write!(f, "/{}", self.component)?;
+        }
+        Ok(())
+    }
+}
+
+/// Value of the Priority field, from most to least important.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum Priority {
+    Required,
+    Important,
+    Standard,
+    Optional,
+    Extra,
+}
+
+impl FromStr for Priority {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "required" => Ok(Priority::Required),
+            "important" => Ok(Priority::Important),
+            "standard" => Ok(Priority::Standard),
+            "optional" => Ok(Priority::Optional),
+            "extra" => Ok(Priority::Extra),
+            _ => Err(format!("Unknown priority: {}", s)),
+        }
+    }
+}
+
+/// Value of the Multi-Arch field.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash)]
+pub enum MultiArch {
+    #[default]
+    No,
+    /// Co-installable with instances of other architectures.
+    Same,
+    /// Satisfies dependencies of packages of any architecture.
+    Foreign,
+    /// Satisfies `:any` dependencies of packages of any architecture.
+    Allowed,
+}
+
+impl FromStr for MultiArch {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "no" => Ok(MultiArch::No),
+            "same" => Ok(MultiArch::Same),
+            "foreign" => Ok(MultiArch::Foreign),
+            "allowed" => Ok(MultiArch::Allowed),
+            _ => Err(format!("Unknown Multi-Arch value: {}", s)),
+        }
+    }
+}
+
+/// A package version listing a virtual package in its Provides field.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Provider {
+    /// Architecture-qualified name of the providing package.
+    pub name: PackageName,
+    pub version: DebianVersion,
+    /// Version of the virtual package, if the provide is versioned (`Provides: foo (= 1.0)`).
+    pub provided_version: Option<DebianVersion>,
+}
+
+impl Provider {
+    /// Whether this provide satisfies a relationship on the virtual package with the given range.
+    ///
+    /// As in dpkg, an unversioned provide only satisfies unversioned relationships.
+    pub fn satisfies(&self, range: &Range<DebianVersion>) -> bool {
+        match &self.provided_version {
+            Some(provided) => range.contains(provided),
+            None => *range == Range::full(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Dependency {
     pub alternatives: Vec<Alternative>,
@@ -40,8 +231,8 @@ pub struct Dependency {
 pub struct Alternative {
     pub name: PackageName,
     pub range: HashedRange,
-    // TODO later
-    // pub arch: Option<Vec<String>>,
+    /// Architecture qualifier, as in `python3:any` or `libc6:i386`.
+    pub arch: Option<String>,
 }
 
 impl Display for Dependency {
@@ -55,11 +246,37 @@ impl Display for Dependency {
     }
 }
 
+fn push_alternative(alternatives: &mut Vec<Alternative>, name: &str, range: Range<DebianVersion>) {
+    match alternatives.iter_mut().find(|alt| alt.name == name) {
+        Some(alt) => alt.range = HashedRange(alt.range.0.union(&range)),
+        None => alternatives.push(Alternative {
+            name: name.to_string(),
+            range: HashedRange(range),
+            arch: None,
+        }),
+    }
+}
+
+/// The package name of an architecture-qualified name.
+pub fn unqualified(name: &str) -> &str {
+    name.split(':').next().unwrap_or(name)
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Index {
     /// Empty new index.
     pub fn new() -> Self {
         Self {
             packages: Map::default(),
+            providers: Map::default(),
+            native_arch: "amd64".to_string(),
+            foreign_archs: Vec::new(),
+            preferences: Preferences::default(),
             debug: false.into(),
             version_debug: false.into(),
         }
@@ -81,7 +298,315 @@ impl Index {
         self.packages
             .entry(name.to_string())
             .or_default()
-            .insert(version, dependencies);
+            .entry(version)
+            .or_default()
+            .depends = dependencies;
+    }
+
+    /// Register the pre-dependencies of a package in the index.
+    pub fn add_pre_deps(
+        &mut self,
+        name: &str,
+        version: DebianVersion,
+        pre_dependencies: Vec<Dependency>,
+    ) {
+        self.packages
+            .entry(name.to_string())
+            .or_default()
+            .entry(version)
+            .or_default()
+            .pre_depends = pre_dependencies;
+    }
+
+    /// Register the optional relationships of a package in the index.
+    pub fn add_soft_deps(
+        &mut self,
+        name: &str,
+        version: DebianVersion,
+        recommends: Vec<Dependency>,
+        suggests: Vec<Dependency>,
+        enhances: Vec<Dependency>,
+    ) {
+        let entry = self
+            .packages
+            .entry(name.to_string())
+            .or_default()
+            .entry(version)
+            .or_default();
+        entry.recommends = recommends;
+        entry.suggests = suggests;
+        entry.enhances = enhances;
+    }
+
+    /// Register the packages a given package version conflicts with or breaks.
+    pub fn add_conflicts(
+        &mut self,
+        name: &str,
+        version: DebianVersion,
+        conflicts: Vec<Alternative>,
+        breaks: Vec<Alternative>,
+    ) {
+        let entry = self
+            .packages
+            .entry(name.to_string())
+            .or_default()
+            .entry(version)
+            .or_default();
+        entry.conflicts = conflicts;
+        entry.breaks = breaks;
+    }
+
+    /// Register the architecture and Multi-Arch value of a package version.
+    pub fn add_arch(
+        &mut self,
+        name: &str,
+        version: DebianVersion,
+        architecture: &str,
+        multi_arch: MultiArch,
+    ) {
+        let entry = self
+            .packages
+            .entry(name.to_string())
+            .or_default()
+            .entry(version)
+            .or_default();
+        entry.architecture = architecture.to_string();
+        entry.multi_arch = multi_arch;
+    }
+
+    /// Register the Essential flag and priority of a package version.
+    pub fn add_essential(
+        &mut self,
+        name: &str,
+        version: DebianVersion,
+        essential: bool,
+        priority: Option<Priority>,
+    ) {
+        let entry = self
+            .packages
+            .entry(name.to_string())
+            .or_default()
+            .entry(version)
+            .or_default();
+        entry.essential = essential;
+        entry.priority = priority;
+    }
+
+    /// Record a repository a package version is available from.
+    pub fn add_origin(&mut self, name: &str, version: DebianVersion, origin: Origin) {
+        let entry = self
+            .packages
+            .entry(name.to_string())
+            .or_default()
+            .entry(version)
+            .or_default();
+        if !entry.origins.contains(&origin) {
+            entry.origins.push(origin);
+        }
+    }
+
+    /// Repositories a package version is available from.
+    pub fn origins(&self, name: &str, version: &DebianVersion) -> &[Origin] {
+        self.packages
+            .get(name)
+            .and_then(|versions| versions.get(version))
+            .map_or(&[], |entry| &entry.origins)
+    }
+
+    /// Names of the packages every system has installed: those whose newest version is
+    /// Essential, plus, if given, those of at least the given priority.
+    pub fn essential_packages(&self, priority: Option<Priority>) -> Vec<PackageName> {
+        let mut names: Vec<PackageName> = self
+            .packages
+            .iter()
+            .filter(|(name, _)| !name.contains(':'))
+            .filter(|(_, versions)| {
+                versions.values().next_back().is_some_and(|entry| {
+                    entry.essential
+                        || matches!((entry.priority, priority), (Some(p), Some(min)) if p <= min)
+                })
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        names.sort();
+        names
+    }
+
+    /// Record that a package version provides a virtual package, optionally at a given version.
+    pub fn add_provider(
+        &mut self,
+        virtual_name: &str,
+        provider: &str,
+        version: DebianVersion,
+        provided_version: Option<DebianVersion>,
+    ) {
+        self.packages
+            .entry(provider.to_string())
+            .or_default()
+            .entry(version.clone())
+            .or_default()
+            .provides
+            .push(virtual_name.to_string());
+        self.providers
+            .entry(virtual_name.to_string())
+            .or_default()
+            .push(Provider {
+                name: provider.to_string(),
+                version,
+                provided_version,
+            });
+    }
+
+    /// Configure the native and foreign architectures.
+    ///
+    /// This decides how packages are keyed, so it must be called before any is registered.
+    pub fn set_architectures(&mut self, native: &str, foreign: &[&str]) {
+        self.native_arch = native.to_string();
+        self.foreign_archs = foreign.iter().map(|arch| arch.to_string()).collect();
+    }
+
+    /// Native architecture followed by the foreign ones.
+    pub fn architectures(&self) -> impl Iterator<Item = &str> {
+        std::iter::once(self.native_arch.as_str())
+            .chain(self.foreign_archs.iter().map(|a| a.as_str()))
+    }
+
+    /// Whether packages of this architecture can be installed.
+    pub fn accepts_arch(&self, arch: &str) -> bool {
+        arch.is_empty() || arch == "all" || self.architectures().any(|a| a == arch)
+    }
+
+    /// The architecture a package is installed as: `Architecture: all` counts as native.
+    pub fn effective_arch<'a>(&'a self, arch: &'a str) -> &'a str {
+        match arch {
+            "" | "all" => &self.native_arch,
+            arch => arch,
+        }
+    }
+
+    /// Index key of the instance of a package for an architecture.
+    pub fn qualify(&self, name: &str, arch: &str) -> PackageName {
+        let arch = self.effective_arch(arch);
+        if arch == self.native_arch {
+            name.to_string()
+        } else {
+            format!("{}:{}", name, arch)
+        }
+    }
+
+    /// Whether a package instance satisfies a relationship with the given architecture
+    /// qualifier, declared by a package of architecture `from`.
+    fn satisfies_arch(&self, qualifier: Option<&str>, from: &str, entry: &Entry) -> bool {
+        let arch = self.effective_arch(&entry.architecture);
+        match qualifier {
+            None => arch == from || entry.multi_arch == MultiArch::Foreign,
+            Some("any") => {
+                arch == from || matches!(entry.multi_arch, MultiArch::Foreign | MultiArch::Allowed)
+            }
+            Some("native") => arch == self.native_arch,
+            Some(qualifier) => arch == qualifier,
+        }
+    }
+
+    /// Expand an alternative into the concrete package instances that can satisfy it when
+    /// declared by a package of architecture `arch`.
+    ///
+    /// These are the instances of the named package whose architecture and Multi-Arch allow
+    /// it, and every matching provider. Alternatives resolving to the same instance are merged.
+    pub fn resolve_alternative(&self, alternative: &Alternative, arch: &str) -> Vec<Alternative> {
+        let from = self.effective_arch(arch);
+        let qualifier = alternative.arch.as_deref();
+        let range = &alternative.range.0;
+        let mut resolved = Vec::new();
+        for candidate in self.architectures() {
+            let key = self.qualify(&alternative.name, candidate);
+            let Some(versions) = self.packages.get(&key) else {
+                continue;
+            };
+            let in_range: Vec<_> = versions.iter().filter(|(v, _)| range.contains(v)).collect();
+            let usable: Vec<_> = in_range
+                .iter()
+                .filter(|(_, entry)| self.satisfies_arch(qualifier, from, entry))
+                .map(|(v, _)| (*v).clone())
+                .collect();
+            if usable.is_empty() {
+                continue;
+            }
+            if usable.len() == in_range.len() {
+                push_alternative(&mut resolved, &key, range.clone());
+            } else {
+                for version in usable {
+                    push_alternative(&mut resolved, &key, Range::singleton(version));
+                }
+            }
+        }
+        for provider in self.providers.get(&alternative.name).into_iter().flatten() {
+            let usable = self
+                .packages
+                .get(&provider.name)
+                .and_then(|versions| versions.get(&provider.version))
+                .is_some_and(|entry| self.satisfies_arch(qualifier, from, entry));
+            if usable && provider.satisfies(range) {
+                push_alternative(
+                    &mut resolved,
+                    &provider.name,
+                    Range::singleton(provider.version.clone()),
+                );
+            }
+        }
+        if resolved.is_empty() {
+            // Keep the requested instance so that the solver can report it as missing.
+            let target = match qualifier {
+                None | Some("any") => from,
+                Some(arch) => arch,
+            };
+            push_alternative(
+                &mut resolved,
+                &self.qualify(&alternative.name, target),
+                range.clone(),
+            );
+        }
+        resolved
+    }
+
+    /// Expand every alternative of a dependency, see [`Index::resolve_alternative`].
+    pub fn resolve_dependency(&self, dependency: &Dependency, arch: &str) -> Dependency {
+        let mut alternatives = Vec::new();
+        for alternative in &dependency.alternatives {
+            for resolved in self.resolve_alternative(alternative, arch) {
+                push_alternative(&mut alternatives, &resolved.name, resolved.range.0);
+            }
+        }
+        Dependency { alternatives }
+    }
+
+    /// Expand the target of a Conflicts or Breaks into the package instances it matches.
+    ///
+    /// Unqualified names match instances of every architecture, and all matching providers.
+    pub fn resolve_conflict(&self, conflict: &Alternative) -> Vec<Alternative> {
+        let range = &conflict.range.0;
+        let mut resolved = Vec::new();
+        for candidate in self.architectures() {
+            let matches = match conflict.arch.as_deref() {
+                None | Some("any") => true,
+                Some("native") => candidate == self.native_arch,
+                Some(arch) => candidate == arch,
+            };
+            let key = self.qualify(&conflict.name, candidate);
+            if matches && self.packages.contains_key(&key) {
+                push_alternative(&mut resolved, &key, range.clone());
+            }
+        }
+        for provider in self.providers.get(&conflict.name).into_iter().flatten() {
+            if provider.satisfies(range) {
+                push_alternative(
+                    &mut resolved,
+                    &provider.name,
+                    Range::singleton(provider.version.clone()),
+                );
+            }
+        }
+        resolved
     }
 
     pub fn set_debug(&self, flag: bool) {
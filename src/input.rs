@@ -0,0 +1,172 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::deb::{load_deb, load_deb_directory};
+use crate::error::{Diagnostics, ParseError, ParseErrorKind};
+use crate::index::{Index, Origin};
+use crate::openpgp::Keyring;
+use crate::parse::load_repository;
+use crate::preferences::load_preferences;
+use crate::release::{load_mirror, load_signed_mirror};
+use crate::sources::{load_lists, load_sources};
+
+/// Where packages are read from, written `<kind>:<path>`, such as
+/// `mirror:debian/dists/bookworm`. A path without a kind is a Packages file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Input {
+    /// A Packages file, possibly compressed, with its origin taken from its path, see
+    /// [`Origin::from_path`]. Written `packages:<path>` or as the bare path.
+    Packages(PathBuf),
+    /// A local mirror of a suite, such as `debian/dists/bookworm`, whose Packages files
+    /// are verified against its Release file. Written `mirror:<dir>`.
+    Mirror(PathBuf),
+    /// A system root such as `/`, giving the packages apt sees from its sources and lists
+    /// directory, with its preferences. Written `root:<dir>`.
+    Root(PathBuf),
+    /// A `.deb` file. Written `deb:<path>`.
+    Deb(PathBuf),
+    /// A directory of `.deb` files, such as a build output directory. Written `debs:<dir>`.
+    DebDirectory(PathBuf),
+}
+
+impl Input {
+    pub fn path(&self) -> &Path {
+        match self {
+            Input::Packages(path)
+            | Input::Mirror(path)
+            | Input::Root(path)
+            | Input::Deb(path)
+            | Input::DebDirectory(path) => path,
+        }
+    }
+}
+
+impl FromStr for Input {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((kind, path)) = s.split_once(':') else {
+            return Ok(Input::Packages(PathBuf::from(s)));
+        };
+        let path = PathBuf::from(path);
+        match kind {
+            "packages" => Ok(Input::Packages(path)),
+            "mirror" => Ok(Input::Mirror(path)),
+            "root" => Ok(Input::Root(path)),
+            "deb" => Ok(Input::Deb(path)),
+            "debs" => Ok(Input::DebDirectory(path)),
+            _ => Err(format!("Unknown input kind: {}", kind)),
+        }
+    }
+}
+
+/// Load the packages of inputs into an index, in order.
+///
+/// With a keyring, the Release files of mirrors and of the lists of system roots are
+/// verified against it, and other inputs, which can not be verified, are refused before
+/// anything is loaded.
+pub fn load_inputs(
+    index: &mut Index,
+    inputs: &[Input],
+    keyring: Option<&Keyring>,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ParseError> {
+    if keyring.is_some() {
+        let unverifiable = inputs
+            .iter()
+            .find(|input| !matches!(input, Input::Mirror(_) | Input::Root(_)));
+        if let Some(input) = unverifiable {
+            let message = "can not be verified against the keyring".to_string();
+            return Err(
+                ParseError::new(ParseErrorKind::BadSignature(message)).in_file(input.path())
+            );
+        }
+    }
+    for input in inputs {
+        match (input, keyring) {
+            (Input::Packages(path), _) => {
+                load_repository(index, path, &Origin::from_path(path), diagnostics)?
+            }
+            (Input::Mirror(dir), Some(keyring)) => {
+                load_signed_mirror(index, dir, keyring, diagnostics)?;
+            }
+            (Input::Mirror(dir), None) => {
+                load_mirror(index, dir, diagnostics)?;
+            }
+            (Input::Root(root), keyring) => {
+                let etc = root.join("etc/apt");
+                let entries = load_sources(&etc, diagnostics)?;
+                let lists = root.join("var/lib/apt/lists");
+                load_lists(index, &entries, lists, keyring, diagnostics)?;
+                index.preferences = load_preferences(&etc, diagnostics)?;
+            }
+            (Input::Deb(path), _) => load_deb(index, path, diagnostics)?,
+            (Input::DebDirectory(dir), _) => load_deb_directory(index, dir, diagnostics)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+    use std::fs;
+
+    use super::*;
+    use crate::debian_version::DebianVersion;
+
+    #[test]
+    fn test_parse_input() {
+        let input = |s: &str| s.parse::<Input>();
+        assert_eq!(
+            input("repositories/buster/Packages"),
+            Ok(Input::Packages(PathBuf::from(
+                "repositories/buster/Packages"
+            )))
+        );
+        assert_eq!(
+            input("mirror:debian/dists/bookworm"),
+            Ok(Input::Mirror(PathBuf::from("debian/dists/bookworm")))
+        );
+        assert_eq!(input("root:/"), Ok(Input::Root(PathBuf::from("/"))));
+        assert_eq!(input("deb:a.deb"), Ok(Input::Deb(PathBuf::from("a.deb"))));
+        assert_eq!(
+            input("debs:out"),
+            Ok(Input::DebDirectory(PathBuf::from("out")))
+        );
+        assert!(input("dir:out").is_err());
+    }
+
+    #[test]
+    fn test_load_inputs() -> Result<(), Box<dyn Error>> {
+        let tmp = tempfile::tempdir()?;
+        let packages = tmp.path().join("Packages");
+        fs::write(&packages, "Package: hello\nVersion: 2.10-3\n")?;
+        let inputs = [Input::Packages(packages)];
+        let mut index = Index::new();
+        load_inputs(&mut index, &inputs, None, &mut Diagnostics::default())?;
+        assert_eq!(
+            index.available_versions(&"hello".to_string()),
+            [DebianVersion("2.10-3".to_string())]
+        );
+
+        // A Packages file given alone can not be verified.
+        let mut index = Index::new();
+        let keyring = Keyring::default();
+        let refused = load_inputs(
+            &mut index,
+            &inputs,
+            Some(&keyring),
+            &mut Diagnostics::default(),
+        );
+        assert!(matches!(
+            refused.unwrap_err().kind,
+            ParseErrorKind::BadSignature(_)
+        ));
+        assert!(index.available_versions(&"hello".to_string()).is_empty());
+
+        // A directory given as a mirror needs a Release file.
+        let mirror = [Input::Mirror(tmp.path().to_path_buf())];
+        assert!(load_inputs(&mut index, &mirror, None, &mut Diagnostics::default()).is_err());
+        Ok(())
+    }
+}
@@ -1,4 +1,19 @@
+pub mod arch;
+pub mod compression;
+pub mod deb;
+pub mod deb822;
 pub mod debian_deps;
 pub mod debian_version;
+pub mod error;
 pub mod index;
+pub mod input;
+pub mod mirror;
+pub mod openpgp;
 pub mod parse;
+pub mod preferences;
+pub mod profile;
+pub mod release;
+pub mod source;
+pub mod sources;
+pub mod status;
+pub mod upgrade;
@@ -1,61 +1,99 @@
-use pubgrub::{
-    DefaultStringReporter, Dependencies, DependencyProvider, PubGrubError, Reporter,
-    SelectedDependencies,
-};
-use pubgrub_debian::debian_deps::Package;
+use pubgrub::{Dependencies, DependencyProvider, Range};
+use pubgrub_debian::debian_deps::{not_installed, removed, Package, Solution, Solver};
 use pubgrub_debian::debian_version::DebianVersion;
+use pubgrub_debian::error::{Diagnostics, Mode};
 use pubgrub_debian::index::Index;
-use pubgrub_debian::parse::create_index;
+use pubgrub_debian::input::{load_inputs, Input};
+use pubgrub_debian::mirror::{selected_packages, write_mirror};
+use pubgrub_debian::openpgp::{load_keyring, Keyring};
+use pubgrub_debian::preferences::load_preferences;
+use pubgrub_debian::source::{parse_source_control, solve_source_control};
+use pubgrub_debian::status::{load_status, InstalledSet};
+use pubgrub_debian::upgrade::{simulate_release_upgrade, solve_upgrade, UpgradeMode};
 use std::collections::{BTreeMap, HashSet};
 use std::error::Error;
 use std::str::FromStr;
 
+/// The inputs of a command line argument, separated by commas, see [`Input`].
+///
+/// E.g. `mirror:debian/dists/bookworm,debs:build` gives the packages of a mirror of a
+/// suite together with the `.deb` files of a build output directory.
+fn parse_inputs(repo: &str) -> Result<Vec<Input>, Box<dyn Error>> {
+    Ok(repo
+        .split(',')
+        .map(Input::from_str)
+        .collect::<Result<_, _>>()?)
+}
+
+/// Load the inputs of a command line argument, skipping malformed stanzas with a warning.
+///
+/// Mirrors and system roots are verified against the keyring if one was given with
+/// `--keyring`, and other inputs refused, see [`load_inputs`].
+fn load_index(repo: &str, keyring: Option<&Keyring>) -> Result<Index, Box<dyn Error>> {
+    let mut index = Index::new();
+    let mut diagnostics = Diagnostics::new(Mode::Lenient);
+    load_inputs(&mut index, &parse_inputs(repo)?, keyring, &mut diagnostics)?;
+    for warning in &diagnostics.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    Ok(index)
+}
+
 fn solve_repo(
     pkg: Package,
     version: DebianVersion,
     repo: &str,
-) -> Result<SelectedDependencies<Index>, Box<dyn Error>> {
-    let index = create_index(repo.to_string())?;
+) -> Result<Solution, Box<dyn Error>> {
+    solve_index(pkg, version, &load_index(repo, None)?)
+}
+
+fn solve_index(
+    pkg: Package,
+    version: DebianVersion,
+    index: &Index,
+) -> Result<Solution, Box<dyn Error>> {
     index.set_debug(true);
 
-    let sol: SelectedDependencies<Index> = match pubgrub::resolve(&index, pkg, version) {
-        Ok(sol) => Ok(sol),
-        Err(PubGrubError::NoSolution(mut derivation_tree)) => {
-            derivation_tree.collapse_no_versions();
-            eprintln!("\n\n\n{}", DefaultStringReporter::report(&derivation_tree));
-            Err(PubGrubError::<Index>::NoSolution(derivation_tree))
-        }
-        Err(err) => panic!("{:?}", err),
-    }?;
+    let sol = Solver::from(index)
+        .resolve(pkg, version)
+        .inspect_err(|err| eprintln!("\n\n\n{}", err))?;
 
     index.set_debug(false);
 
     fn get_resolved_deps<'a>(
         index: &'a Index,
-        sol: &'a SelectedDependencies<Index>,
+        sol: &'a Solution,
         package: &Package,
         version: &'a DebianVersion,
     ) -> HashSet<(String, &'a DebianVersion)> {
-        let dependencies = index.get_dependencies(&package, &version);
+        let dependencies = Solver::from(index).get_dependencies(package, version);
         match dependencies {
             Ok(Dependencies::Available(constraints)) => {
                 let mut dependents = HashSet::new();
                 for (dep_package, _dep_versions) in constraints {
                     let solved_version = sol.get(&dep_package).unwrap();
+                    if *solved_version == not_installed() {
+                        continue;
+                    }
                     match dep_package.clone() {
                         Package::Base(name) => {
                             dependents.insert((name, solved_version));
                         }
-                        Package::Proxy(_) => {
+                        Package::Proxy(_) | Package::Optional(_) => {
                             dependents.extend(get_resolved_deps(
-                                &index,
+                                index,
                                 sol,
                                 &dep_package,
                                 solved_version,
                             ));
                         }
                         Package::Root(_deps) => {
-                            dependents.extend(get_resolved_deps(&index, sol, &dep_package, solved_version));
+                            dependents.extend(get_resolved_deps(
+                                index,
+                                sol,
+                                &dep_package,
+                                solved_version,
+                            ));
                         }
                     };
                 }
@@ -70,33 +108,41 @@ fn solve_repo(
 
     println!("\nSolution Set:");
     for (package, version) in &sol {
-        match package {
-            Package::Base(name) => {
-                println!("\t({}, {})", name, version);
+        if let Package::Base(name) = package {
+            if *version != not_installed() {
+                print!("\t({}, {})", name, version);
+                let origins: Vec<String> = index
+                    .origins(name, version)
+                    .iter()
+                    .map(|origin| origin.to_string())
+                    .collect();
+                if !origins.is_empty() {
+                    print!(" [{}]", origins.join(", "));
+                }
+                println!();
             }
-            _ => (),
         }
     }
 
     let mut resolved_graph: BTreeMap<(String, &DebianVersion), Vec<(String, &DebianVersion)>> =
         BTreeMap::new();
     for (package, version) in &sol {
-        match package {
-            Package::Base(name) => {
-                let mut deps = get_resolved_deps(&index, &sol, &package, version)
-                    .into_iter()
-                    .collect::<Vec<_>>();
-                deps.sort_by(|(p1, _v1), (p2, _v2)| p1.cmp(p2));
-                resolved_graph.insert((name.clone(), version), deps);
+        if let Package::Base(name) = package {
+            if *version == not_installed() {
+                continue;
             }
-            _ => {}
+            let mut deps = get_resolved_deps(index, &sol, package, version)
+                .into_iter()
+                .collect::<Vec<_>>();
+            deps.sort_by(|(p1, _v1), (p2, _v2)| p1.cmp(p2));
+            resolved_graph.insert((name.clone(), version), deps);
         }
     }
 
     println!("\nResolved Dependency Graph:");
     for ((name, version), dependents) in resolved_graph {
         print!("\t({}, {})", name, version);
-        if dependents.len() > 0 {
+        if !dependents.is_empty() {
             print!(" -> ")
         }
         let mut first = true;
@@ -113,19 +159,240 @@ fn solve_repo(
     Ok(sol)
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let _ = solve_repo(
-        Package::from_str("openssh-server").unwrap(),
-        "1:7.9p1-10+deb10u2".parse::<DebianVersion>().unwrap(),
-        "./repositories/buster/Packages",
-    );
+/// Resolve the packages needed to build the source tree of a `debian/control` file.
+///
+/// Active build profiles are read from `DEB_BUILD_PROFILES`, as dpkg-buildpackage does.
+fn build_dep(control: &str, repo: &str, keyring: Option<&Keyring>) -> Result<(), Box<dyn Error>> {
+    let control = parse_source_control(control)?;
+    for substvar in &control.source.substvars {
+        eprintln!("Ignoring substitution variable {}", substvar);
+    }
+    let profiles: HashSet<String> = std::env::var("DEB_BUILD_PROFILES")
+        .unwrap_or_default()
+        .split_whitespace()
+        .map(|profile| profile.to_string())
+        .collect();
+    let mut index = load_index(repo, keyring)?;
+    let sol = solve_source_control(&mut index, &control, &profiles)?;
+    let build_deps = format!("{}-build-deps", control.source.package);
+
+    println!("\nBuild Dependencies:");
+    let mut packages: Vec<_> = sol
+        .iter()
+        .filter_map(|(package, version)| match package {
+            Package::Base(name) if *version != not_installed() && *name != build_deps => {
+                Some((name, version))
+            }
+            _ => None,
+        })
+        .collect();
+    packages.sort();
+    for (name, version) in packages {
+        println!("\t({}, {})", name, version);
+    }
+    Ok(())
+}
+
+/// Solve for a package and write a minimal mirror holding only the packages of the solution.
+fn mirror(
+    repo: &str,
+    package: &str,
+    version: &str,
+    dist: &str,
+    keyring: Option<&Keyring>,
+) -> Result<(), Box<dyn Error>> {
+    let inputs = parse_inputs(repo)?;
+    let [Input::Packages(packages)] = &inputs[..] else {
+        return Err(format!("mirror takes a single Packages file, not {}", repo).into());
+    };
+    let index = load_index(repo, keyring)?;
+    let sol = solve_index(Package::from_str(package)?, version.parse()?, &index)?;
+    write_mirror(&index, packages, &selected_packages(&sol), dist, "main")
+}
+
+/// Solve against the system described by a dpkg status file and print the changes to make.
+fn upgrade(
+    status: &str,
+    repo: &str,
+    mode: UpgradeMode,
+    packages: &[String],
+    keyring: Option<&Keyring>,
+) -> Result<(), Box<dyn Error>> {
+    let mut index = load_index(repo, keyring)?;
+    let mut diagnostics = Diagnostics::new(Mode::Lenient);
+    let installed = load_status(&mut index, status, &mut diagnostics)?;
+    for warning in &diagnostics.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    // As with apt-get install, a trailing '-' removes the package.
+    let requests = packages
+        .iter()
+        .map(|package| match package.strip_suffix('-') {
+            Some(package) => Ok((Package::from_str(package)?, removed())),
+            None => Ok((Package::from_str(package)?, Range::full())),
+        })
+        .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+    let changes = solve_upgrade(&index, &installed, mode, requests).inspect_err(|err| {
+        eprintln!("{}", err);
+    })?;
+    for change in changes {
+        println!("{}", change);
+    }
     Ok(())
 }
 
+/// Simulate moving a system to the release of `target`.
+///
+/// The system is read from a dpkg status file or, if packages are given, solved for them
+/// from the Packages file of the current release.
+fn release_upgrade(
+    system: &str,
+    target: &str,
+    packages: &[String],
+    keyring: Option<&Keyring>,
+) -> Result<(), Box<dyn Error>> {
+    let installed = if packages.is_empty() {
+        let mut diagnostics = Diagnostics::new(Mode::Lenient);
+        let installed = load_status(&mut Index::new(), system, &mut diagnostics)?;
+        for warning in &diagnostics.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        installed
+    } else {
+        let index = load_index(system, keyring)?;
+        let requirements = packages
+            .iter()
+            .map(|package| Ok((Package::from_str(package)?, Range::full())))
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        let sol = Solver::from(&index).resolve(Package::Root(requirements), not_installed())?;
+        InstalledSet::from_solution(&sol)
+    };
+    let target = load_index(target, keyring)?;
+    let upgrade = simulate_release_upgrade(&target, &installed).inspect_err(|err| {
+        eprintln!("{}", err);
+    })?;
+
+    println!("Changes:");
+    for change in &upgrade.changes {
+        println!("\t{}", change);
+    }
+    println!("\nRemovals:");
+    for removal in &upgrade.removals {
+        println!("\t({}, {})", removal.package, removal.version);
+        for line in removal.reason.lines() {
+            println!("\t\t{}", line);
+        }
+    }
+    println!("\nObsolete:");
+    for package in &upgrade.obsolete {
+        println!("\t({}, {})", package.name, package.version);
+    }
+    println!("\nResulting Package Set:");
+    for (name, version) in &upgrade.packages {
+        println!("\t({}, {})", name, version);
+    }
+    Ok(())
+}
+
+/// Show the priorities of the versions of packages and their candidates, as
+/// `apt-cache policy` does, given the preferences of an apt configuration directory.
+fn policy(
+    repo: &str,
+    etc: &str,
+    packages: &[String],
+    keyring: Option<&Keyring>,
+) -> Result<(), Box<dyn Error>> {
+    let mut index = load_index(repo, keyring)?;
+    let mut diagnostics = Diagnostics::new(Mode::Lenient);
+    index.preferences = load_preferences(etc, &mut diagnostics)?;
+    for warning in &diagnostics.warnings {
+        eprintln!("warning: {}", warning);
+    }
+    for name in packages {
+        println!("{}:", name);
+        match index.candidate(name, None) {
+            Some(candidate) => println!("  Candidate: {}", candidate),
+            None => println!("  Candidate: (none)"),
+        }
+        println!("  Version table:");
+        for version in index.available_versions(name) {
+            println!("     {} {}", version, index.pin_priority(name, &version));
+            for origin in index.origins(name, &version) {
+                println!("        {}", origin);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args: Vec<String> = std::env::args().collect();
+    // Keys that mirror directories must be signed with.
+    let mut keyring = None;
+    if let Some(i) = args.iter().position(|arg| arg == "--keyring") {
+        let Some(path) = args.get(i + 1) else {
+            return Err("usage: pubgrub_debian --keyring <keyring> <command> ...".into());
+        };
+        keyring = Some(load_keyring(path)?);
+        args.drain(i..i + 2);
+    }
+    let keyring = keyring.as_ref();
+    match args.get(1).map(String::as_str) {
+        Some("build-dep") => match (args.get(2), args.get(3)) {
+            (Some(control), Some(repo)) => build_dep(control, repo, keyring),
+            _ => Err("usage: pubgrub_debian build-dep <debian/control> <Packages>".into()),
+        },
+        Some("mirror") => match &args[2..] {
+            [repo, package, version, dist] => mirror(repo, package, version, dist, keyring),
+            _ => {
+                Err("usage: pubgrub_debian mirror <Packages> <package> <version> <dist-dir>".into())
+            }
+        },
+        Some("install") => match &args[2..] {
+            [status, repo, packages @ ..] if !packages.is_empty() => {
+                upgrade(status, repo, UpgradeMode::MinimalChange, packages, keyring)
+            }
+            _ => Err("usage: pubgrub_debian install <status> <Packages> <package>[-]...".into()),
+        },
+        Some("remove") => match &args[2..] {
+            [status, repo, packages @ ..] if !packages.is_empty() => {
+                let packages: Vec<String> = packages.iter().map(|p| format!("{}-", p)).collect();
+                upgrade(status, repo, UpgradeMode::MinimalChange, &packages, keyring)
+            }
+            _ => Err("usage: pubgrub_debian remove <status> <Packages> <package>...".into()),
+        },
+        Some("upgrade") => match &args[2..] {
+            [status, repo] => upgrade(status, repo, UpgradeMode::Upgrade, &[], keyring),
+            _ => Err("usage: pubgrub_debian upgrade <status> <Packages>".into()),
+        },
+        Some("dist-upgrade") => match &args[2..] {
+            [status, repo] => upgrade(status, repo, UpgradeMode::DistUpgrade, &[], keyring),
+            _ => Err("usage: pubgrub_debian dist-upgrade <status> <Packages>".into()),
+        },
+        Some("policy") => match &args[2..] {
+            [repo, etc, packages @ ..] if !packages.is_empty() => policy(repo, etc, packages, keyring),
+            _ => Err("usage: pubgrub_debian policy <Packages> <etc-apt-dir> <package>...".into()),
+        },
+        Some("release-upgrade") => match &args[2..] {
+            [system, target, packages @ ..] => release_upgrade(system, target, packages, keyring),
+            _ => Err(
+                "usage: pubgrub_debian release-upgrade <status|Packages> <target Packages> [<package>...]"
+                    .into(),
+            ),
+        },
+        _ => {
+            let _ = solve_repo(
+                Package::from_str("openssh-server").unwrap(),
+                "1:7.9p1-10+deb10u2".parse::<DebianVersion>().unwrap(),
+                "./repositories/buster/Packages",
+            );
+            Ok(())
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use pubgrub::Range;
-
     use super::*;
 
     #[test]
@@ -0,0 +1,208 @@
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::compression;
+use crate::deb822::{parse_paragraphs, write_paragraphs, Field, Paragraph};
+use crate::debian_deps::{not_installed, Package, Solution};
+use crate::debian_version::DebianVersion;
+use crate::index::Index;
+use crate::release::format_date;
+
+/// The `(name, version)` pairs of the packages installed by a solution, keyed by index name.
+pub fn selected_packages(solution: &Solution) -> HashSet<(String, DebianVersion)> {
+    solution
+        .iter()
+        .filter_map(|(package, version)| match package {
+            Package::Base(name) if *version != not_installed() => {
+                Some((name.clone(), version.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// The stanzas of a Packages file for the selected `(name, version)` pairs.
+///
+/// Names are matched as the index qualifies them, so foreign architecture packages are
+/// selected as `name:arch`.
+pub fn filter_packages(
+    index: &Index,
+    paragraphs: &[Paragraph],
+    selected: &HashSet<(String, DebianVersion)>,
+) -> Vec<Paragraph> {
+    paragraphs
+        .iter()
+        .filter(|paragraph| {
+            let (Some(package), Some(version)) = (paragraph.package(), paragraph.version()) else {
+                return false;
+            };
+            let name = index.qualify(&package, &paragraph.architecture().unwrap_or_default());
+            selected.contains(&(name, version))
+        })
+        .cloned()
+        .collect()
+}
+
+/// A Release file for the given files, with their `MD5Sum` and `SHA256` checksums.
+///
+/// File paths are relative to the directory of the Release file, e.g.
+/// `main/binary-amd64/Packages`.
+pub fn release_file(mut fields: Vec<Field>, files: &[(String, Vec<u8>)]) -> Paragraph {
+    let checksums = |hash: &dyn Fn(&[u8]) -> String| {
+        files
+            .iter()
+            .map(|(path, content)| format!("\n {} {:>16} {}", hash(content), content.len(), path))
+            .collect::<String>()
+    };
+    fields.push(Field {
+        name: "MD5Sum".to_string(),
+        value: checksums(&|content| format!("{:x}", Md5::digest(content))),
+    });
+    fields.push(Field {
+        name: "SHA256".to_string(),
+        value: checksums(&|content| format!("{:x}", Sha256::digest(content))),
+    });
+    Paragraph::new(fields)
+}
+
+/// Write a minimal mirror holding only the selected packages of a Packages file, which may
+/// be compressed.
+///
+/// The trimmed Packages files are written to `<dist>/<component>/binary-<arch>/Packages`
+/// for each architecture of the index, `Architecture: all` packages going to each of them,
+/// with a matching `<dist>/Release` naming the suite after the `<dist>` directory.
+pub fn write_mirror<P: AsRef<Path>, Q: AsRef<Path>>(
+    index: &Index,
+    packages: P,
+    selected: &HashSet<(String, DebianVersion)>,
+    dist: Q,
+    component: &str,
+) -> Result<(), Box<dyn Error>> {
+    let paragraphs = parse_paragraphs(&compression::read_to_string(packages)?)?;
+    let filtered = filter_packages(index, &paragraphs, selected);
+
+    let dist = dist.as_ref();
+    let architectures: Vec<&str> = index.architectures().collect();
+    let mut files = Vec::new();
+    for arch in &architectures {
+        let stanzas: Vec<Paragraph> = filtered
+            .iter()
+            .filter(|paragraph| {
+                let architecture = paragraph.architecture().unwrap_or_default();
+                architecture == "all" || index.effective_arch(&architecture) == *arch
+            })
+            .cloned()
+            .collect();
+        let path = format!("{}/binary-{}/Packages", component, arch);
+        let content = write_paragraphs(&stanzas);
+        fs::create_dir_all(dist.join(&path).parent().unwrap())?;
+        fs::write(dist.join(&path), &content)?;
+        files.push((path, content.into_bytes()));
+    }
+
+    let field = |name: &str, value: &str| Field {
+        name: name.to_string(),
+        value: value.to_string(),
+    };
+    let suite = dist
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let release = release_file(
+        vec![
+            field("Suite", &suite),
+            field("Codename", &suite),
+            field("Date", &format_date(SystemTime::now())),
+            field("Architectures", &architectures.join(" ")),
+            field("Components", component),
+        ],
+        &files,
+    );
+    fs::write(dist.join("Release"), release.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Diagnostics;
+    use crate::release::load_mirror;
+
+    const PACKAGES: &str = "Package: a\nVersion: 1\nDepends: b\n\nPackage:  a\r\nVersion: 2\r\nDescription: second\r\n .\r\n  kept as is\r\n\nPackage: b\nVersion: 1\nArchitecture: i386\n";
+
+    #[test]
+    fn test_filter_packages() -> Result<(), Box<dyn Error>> {
+        let mut index = Index::new();
+        index.set_architectures("amd64", &["i386"]);
+        let paragraphs = parse_paragraphs(PACKAGES)?;
+        let selected = HashSet::from([
+            ("a".to_string(), DebianVersion("2".to_string())),
+            ("b:i386".to_string(), DebianVersion("1".to_string())),
+        ]);
+        let filtered = write_paragraphs(&filter_packages(&index, &paragraphs, &selected));
+        assert_eq!(
+            filtered,
+            "Package:  a\r\nVersion: 2\r\nDescription: second\r\n .\r\n  kept as is\r\n\nPackage: b\nVersion: 1\nArchitecture: i386\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_file() {
+        let release = release_file(
+            vec![Field {
+                name: "Components".to_string(),
+                value: "main".to_string(),
+            }],
+            &[("main/binary-amd64/Packages".to_string(), b"".to_vec())],
+        );
+        assert_eq!(
+            release.to_string(),
+            "Components: main\n\
+             MD5Sum:\n d41d8cd98f00b204e9800998ecf8427e                0 main/binary-amd64/Packages\n\
+             SHA256:\n e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855                0 main/binary-amd64/Packages\n"
+        );
+    }
+
+    #[test]
+    fn test_write_mirror() -> Result<(), Box<dyn Error>> {
+        let root = tempfile::tempdir()?;
+        let dist = root.path().join("dists/bookworm");
+        let packages = root.path().join("Packages.gz");
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(
+            &mut gz,
+            format!("{}\nPackage: c\nVersion: 1\nArchitecture: all\n", PACKAGES).as_bytes(),
+        )?;
+        fs::write(&packages, gz.finish()?)?;
+        let mut index = Index::new();
+        index.set_architectures("amd64", &["i386"]);
+        let selected = HashSet::from([
+            ("a".to_string(), DebianVersion("2".to_string())),
+            ("b:i386".to_string(), DebianVersion("1".to_string())),
+            ("c".to_string(), DebianVersion("1".to_string())),
+        ]);
+        write_mirror(&index, &packages, &selected, &dist, "main")?;
+
+        let amd64 = fs::read_to_string(dist.join("main/binary-amd64/Packages"))?;
+        let i386 = fs::read_to_string(dist.join("main/binary-i386/Packages"))?;
+        assert!(amd64.contains("Package:  a\r\n") && !amd64.contains("Package: b\n"));
+        assert!(i386.contains("Package: b\n") && !i386.contains("Package:  a"));
+        assert!(amd64.contains("Package: c\n") && i386.contains("Package: c\n"));
+        let mut mirrored = Index::new();
+        mirrored.set_architectures("amd64", &["i386"]);
+        let release = load_mirror(&mut mirrored, &dist, &mut Diagnostics::default())?;
+        assert_eq!(release.suite, "bookworm");
+        assert!(release.date.is_some());
+        let origins = mirrored.origins("b:i386", &DebianVersion("1".to_string()));
+        assert_eq!(origins.len(), 1);
+        assert_eq!(origins[0].suite, "bookworm");
+        Ok(())
+    }
+}
@@ -0,0 +1,1273 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ed25519_dalek::{Verifier, VerifyingKey};
+use rsa::traits::PublicKeyParts;
+use rsa::{BigUint, Pkcs1v15Sign, RsaPublicKey};
+use sha1::Sha1;
+use sha2::{Digest, Sha224, Sha256, Sha384, Sha512};
+
+use crate::deb822::{PGP_SIGNATURE, PGP_SIGNED_MESSAGE};
+use crate::error::{ParseError, ParseErrorKind};
+
+/// OID of the Ed25519 curve, as written in legacy EdDSA keys.
+const ED25519_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xda, 0x47, 0x0f, 0x01];
+
+/// The largest RSA keys accepted, in bits.
+const RSA_MAX_BITS: usize = 16384;
+
+/// Signature subpackets that are understood, or that only matter to encryption and key
+/// servers, so that signatures marking them critical are not refused.
+const KNOWN_SUBPACKETS: &[u8] = &[2, 3, 9, 11, 16, 21, 22, 23, 24, 25, 27, 29, 30, 32, 33, 34];
+
+fn invalid(message: &str) -> ParseError {
+    ParseError::new(ParseErrorKind::InvalidValue(message.to_string()))
+}
+
+fn bad_signature(message: String) -> ParseError {
+    ParseError::new(ParseErrorKind::BadSignature(message))
+}
+
+/// An OpenPGP v4 fingerprint, the SHA-1 of a public key packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Fingerprint(pub [u8; 20]);
+
+impl Fingerprint {
+    /// The key ID, the last 8 bytes of the fingerprint.
+    pub fn key_id(&self) -> [u8; 8] {
+        self.0[12..].try_into().unwrap()
+    }
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02X}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum KeyMaterial {
+    Rsa(RsaPublicKey),
+    Ed25519(VerifyingKey),
+}
+
+/// A primary key or subkey able to verify signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PublicKey {
+    pub fingerprint: Fingerprint,
+    /// Fingerprint of the primary key, the key itself for primary keys.
+    pub primary: Fingerprint,
+    /// Creation time, in seconds since the Unix epoch.
+    pub created: u64,
+    /// Expiration time, in seconds since the Unix epoch. Subkeys expire with their primary
+    /// key at the latest.
+    pub expires: Option<u64>,
+    /// Whether the key, or the primary key of a subkey, is revoked.
+    pub revoked: bool,
+    material: KeyMaterial,
+}
+
+/// Public keys trusted to sign repositories, such as those of
+/// `/usr/share/keyrings/debian-archive-keyring.gpg`.
+///
+/// Every key of the keyring is trusted, there is no web of trust, but only keys with a
+/// valid self-signature are kept: subkeys must be bound to their primary key and
+/// cross-certify it, and keys not flagged for signing are skipped. Signatures by expired
+/// or revoked keys are refused, as apt refuses them from `gpgv`. Only v4 RSA and Ed25519
+/// keys are kept, keys of other algorithms are skipped with their subkeys.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Keyring {
+    pub keys: Vec<PublicKey>,
+}
+
+/// Decode standard base64, ignoring whitespace.
+fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    let mut output = Vec::with_capacity(input.len() * 3 / 4);
+    let (mut bits, mut count) = (0u32, 0);
+    for c in input.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            b'=' => break,
+            _ => return None,
+        };
+        bits = bits << 6 | u32::from(value);
+        count += 6;
+        if count >= 8 {
+            count -= 8;
+            output.push((bits >> count) as u8);
+        }
+    }
+    Some(output)
+}
+
+/// Decode the first ASCII-armored block of the input, or return binary input as is.
+fn dearmor(input: &[u8]) -> Result<Vec<u8>, ParseError> {
+    let Some(text) = std::str::from_utf8(input)
+        .ok()
+        .filter(|text| text.contains("-----BEGIN PGP "))
+    else {
+        return Ok(input.to_vec());
+    };
+    let mut lines = text
+        .lines()
+        .map(str::trim_end)
+        .skip_while(|line| !line.starts_with("-----BEGIN PGP "))
+        .skip(1);
+    // Skip the armor headers, which end at the first empty line.
+    for line in lines.by_ref() {
+        if line.is_empty() {
+            break;
+        }
+    }
+    let body: String = lines
+        .take_while(|line| !line.starts_with("-----END PGP "))
+        // The CRC-24 checksum line.
+        .filter(|line| !(line.starts_with('=') && line.len() == 5))
+        .collect();
+    base64_decode(&body).ok_or_else(|| invalid("Invalid base64 in ASCII armor"))
+}
+
+/// A packet of an OpenPGP message, given by its tag and body.
+struct Packet<'a> {
+    tag: u8,
+    body: &'a [u8],
+}
+
+/// Read bytes from the front of a slice, failing on truncated input.
+fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], ParseError> {
+    if input.len() < len {
+        return Err(invalid("Truncated OpenPGP packet"));
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Ok(taken)
+}
+
+fn take_u8(input: &mut &[u8]) -> Result<u8, ParseError> {
+    Ok(take(input, 1)?[0])
+}
+
+fn take_u16(input: &mut &[u8]) -> Result<usize, ParseError> {
+    let bytes = take(input, 2)?;
+    Ok(usize::from(bytes[0]) << 8 | usize::from(bytes[1]))
+}
+
+fn take_u32(input: &mut &[u8]) -> Result<usize, ParseError> {
+    let bytes = take(input, 4)?;
+    Ok(u32::from_be_bytes(bytes.try_into().unwrap()) as usize)
+}
+
+/// A multiprecision integer, as its big-endian bytes.
+fn take_mpi<'a>(input: &mut &'a [u8]) -> Result<&'a [u8], ParseError> {
+    let bits = take_u16(input)?;
+    take(input, bits.div_ceil(8))
+}
+
+/// Split binary OpenPGP data into packets, in both the old and the new packet formats.
+fn packets(mut input: &[u8]) -> Result<Vec<Packet<'_>>, ParseError> {
+    let mut packets = Vec::new();
+    while !input.is_empty() {
+        let header = take_u8(&mut input)?;
+        if header & 0x80 == 0 {
+            return Err(invalid("Invalid OpenPGP packet header"));
+        }
+        let (tag, len) = if header & 0x40 != 0 {
+            let len = match take_u8(&mut input)? {
+                first @ 0..=191 => usize::from(first),
+                first @ 192..=223 => {
+                    ((usize::from(first) - 192) << 8) + usize::from(take_u8(&mut input)?) + 192
+                }
+                255 => take_u32(&mut input)?,
+                _ => return Err(invalid("Partial OpenPGP packet lengths are not supported")),
+            };
+            (header & 0x3f, len)
+        } else {
+            let len = match header & 0x03 {
+                0 => usize::from(take_u8(&mut input)?),
+                1 => take_u16(&mut input)?,
+                2 => take_u32(&mut input)?,
+                _ => input.len(),
+            };
+            (header >> 2 & 0x0f, len)
+        };
+        packets.push(Packet {
+            tag,
+            body: take(&mut input, len)?,
+        });
+    }
+    Ok(packets)
+}
+
+/// The current time, in seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// A v4 public key or public subkey packet, as hashed for fingerprints and signatures.
+fn key_data(body: &[u8]) -> Vec<u8> {
+    let mut data = vec![0x99];
+    data.extend((body.len() as u16).to_be_bytes());
+    data.extend(body);
+    data
+}
+
+/// Fingerprint of a v4 public key or public subkey packet.
+fn fingerprint(body: &[u8]) -> Fingerprint {
+    Fingerprint(Sha1::digest(key_data(body)).into())
+}
+
+/// Parse a public key or public subkey packet, returning `None` for unsupported keys.
+fn public_key(body: &[u8], primary: Option<Fingerprint>) -> Result<Option<PublicKey>, ParseError> {
+    let mut input = body;
+    if take_u8(&mut input)? != 4 {
+        return Ok(None);
+    }
+    let created = take_u32(&mut input)? as u64;
+    let material = match take_u8(&mut input)? {
+        // RSA, RSA encrypt-only and RSA sign-only.
+        1..=3 => {
+            let n = BigUint::from_bytes_be(take_mpi(&mut input)?);
+            let e = BigUint::from_bytes_be(take_mpi(&mut input)?);
+            match RsaPublicKey::new_with_max_size(n, e, RSA_MAX_BITS) {
+                Ok(key) => KeyMaterial::Rsa(key),
+                Err(_) => return Ok(None),
+            }
+        }
+        // Legacy EdDSA.
+        22 => {
+            let oid_len = usize::from(take_u8(&mut input)?);
+            if take(&mut input, oid_len)? != ED25519_OID {
+                return Ok(None);
+            }
+            // A native point, prefixed with 0x40.
+            let point = match take_mpi(&mut input)? {
+                [0x40, point @ ..] => point,
+                _ => return Ok(None),
+            };
+            match point
+                .try_into()
+                .map(|point| VerifyingKey::from_bytes(&point))
+            {
+                Ok(Ok(key)) => KeyMaterial::Ed25519(key),
+                _ => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    let fingerprint = fingerprint(body);
+    Ok(Some(PublicKey {
+        fingerprint,
+        primary: primary.unwrap_or(fingerprint),
+        created,
+        expires: None,
+        revoked: false,
+        material,
+    }))
+}
+
+/// Keep the latest of two self-signatures.
+fn latest<'a>(binding: &mut Option<Signature<'a>>, signature: Signature<'a>) {
+    if binding
+        .as_ref()
+        .is_none_or(|binding| binding.created <= signature.created)
+    {
+        *binding = Some(signature);
+    }
+}
+
+/// Whether the key flags of a self-signature, if any, let the key sign data.
+fn can_sign(key_flags: Option<u8>) -> bool {
+    key_flags.is_none_or(|flags| flags & 0x02 != 0)
+}
+
+/// Whether a subkey binding signature embeds a valid back-signature by the subkey, without
+/// which the signing subkey of another certificate could be claimed.
+fn cross_certified(subkey: &PublicKey, data: &[u8], binding: &Signature, now: u64) -> bool {
+    binding.embedded.iter().any(|body| {
+        matches!(signature(body), Ok(Some(back))
+            if back.kind == 0x19 && subkey.check(data, &back, now).is_ok())
+    })
+}
+
+/// The signing keys of a certificate, a public key followed by its revocations, user IDs,
+/// subkeys and their signatures.
+///
+/// The primary key must have a valid self-signature, directly on the key or on a user ID,
+/// and subkeys a valid binding signature. The latest ones give the key expiration time and
+/// flags, those of a direct key signature taking precedence. Valid revocation signatures
+/// mark keys as revoked, and other signatures, such as certifications by other keys, are
+/// ignored.
+fn certificate_keys(packets: &[Packet], now: u64) -> Result<Vec<PublicKey>, ParseError> {
+    let Some(mut primary) = public_key(packets[0].body, None)? else {
+        return Ok(Vec::new());
+    };
+    let primary_data = key_data(packets[0].body);
+    let (mut direct, mut certification) = (None, None);
+    let mut subkeys = Vec::new();
+    // The packet the following signatures are about, and the data they are over.
+    let mut component = 6;
+    let mut data = primary_data.clone();
+    for packet in &packets[1..] {
+        match packet.tag {
+            13 => {
+                component = 13;
+                data = primary_data.clone();
+                data.push(0xb4);
+                data.extend((packet.body.len() as u32).to_be_bytes());
+                data.extend(packet.body);
+            }
+            14 => {
+                component = 14;
+                data = [primary_data.as_slice(), &key_data(packet.body)].concat();
+                subkeys.push((public_key(packet.body, Some(primary.fingerprint))?, None));
+            }
+            // User attributes, whose signatures do not matter.
+            17 => component = 17,
+            2 => {
+                let Some(signature) = signature(packet.body)? else {
+                    continue;
+                };
+                if !signature.may_be_by(&primary) || primary.check(&data, &signature, now).is_err()
+                {
+                    continue;
+                }
+                match (component, signature.kind, subkeys.last_mut()) {
+                    (6, 0x20, _) => primary.revoked = true,
+                    (6, 0x1f, _) => latest(&mut direct, signature),
+                    (13, 0x10..=0x13, _) => latest(&mut certification, signature),
+                    (14, 0x28, Some((Some(subkey), _))) => subkey.revoked = true,
+                    (14, 0x18, Some((Some(subkey), subkey_binding)))
+                        if !can_sign(signature.key_flags)
+                            || cross_certified(subkey, &data, &signature, now) =>
+                    {
+                        latest(subkey_binding, signature)
+                    }
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+    let self_signatures: Vec<&Signature> = direct.iter().chain(&certification).collect();
+    if self_signatures.is_empty() {
+        return Ok(Vec::new());
+    }
+    let key_expires = self_signatures
+        .iter()
+        .find_map(|binding| binding.key_expires);
+    primary.expires = key_expires.map(|expires| primary.created + expires);
+    let mut keys = Vec::new();
+    if can_sign(self_signatures.iter().find_map(|binding| binding.key_flags)) {
+        keys.push(primary.clone());
+    }
+    for (subkey, binding) in subkeys {
+        let (Some(mut subkey), Some(binding)) = (subkey, binding) else {
+            continue;
+        };
+        let expires = binding.key_expires.map(|expires| subkey.created + expires);
+        subkey.expires = expires.into_iter().chain(primary.expires).min();
+        subkey.revoked |= primary.revoked;
+        if can_sign(binding.key_flags) {
+            keys.push(subkey);
+        }
+    }
+    Ok(keys)
+}
+
+/// Parse a keyring, binary as `.gpg` files or ASCII-armored as `.asc` files.
+pub fn parse_keyring(data: &[u8]) -> Result<Keyring, ParseError> {
+    let data = dearmor(data)?;
+    let mut certificates: Vec<Vec<Packet>> = Vec::new();
+    for packet in packets(&data)? {
+        match certificates.last_mut() {
+            _ if packet.tag == 6 => certificates.push(vec![packet]),
+            Some(certificate) => certificate.push(packet),
+            None => {}
+        }
+    }
+    let now = now();
+    let mut keyring = Keyring::default();
+    for certificate in certificates {
+        keyring.keys.extend(certificate_keys(&certificate, now)?);
+    }
+    Ok(keyring)
+}
+
+/// Load a keyring file, or the `.gpg` and `.asc` files of a directory such as
+/// `/etc/apt/trusted.gpg.d`.
+pub fn load_keyring<P: AsRef<Path>>(path: P) -> Result<Keyring, ParseError> {
+    let path = path.as_ref();
+    let mut paths = Vec::new();
+    if path.is_dir() {
+        for entry in fs::read_dir(path).map_err(|e| ParseError::from(e).in_file(path))? {
+            let path = entry?.path();
+            if path
+                .extension()
+                .is_some_and(|extension| extension == "gpg" || extension == "asc")
+            {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+    } else {
+        paths.push(path.to_path_buf());
+    }
+    let mut keyring = Keyring::default();
+    for path in paths {
+        let data = fs::read(&path).map_err(|e| ParseError::from(e).in_file(&path))?;
+        let parsed = parse_keyring(&data).map_err(|e| e.in_file(&path))?;
+        keyring.keys.extend(parsed.keys);
+    }
+    Ok(keyring)
+}
+
+/// A v4 signature packet.
+struct Signature<'a> {
+    kind: u8,
+    hash_algorithm: u8,
+    /// The version, type, algorithms and hashed subpackets, which are hashed with the data.
+    hashed: &'a [u8],
+    /// The issuer, from its fingerprint or key ID subpacket.
+    issuer: Option<Issuer>,
+    /// Creation time, in seconds since the Unix epoch.
+    created: Option<u64>,
+    /// Validity period, in seconds after the creation time.
+    expires: Option<u64>,
+    /// Validity period of the key of a self-signature, in seconds after the key creation.
+    key_expires: Option<u64>,
+    key_flags: Option<u8>,
+    /// Embedded signatures, such as the back-signature of a subkey binding signature.
+    embedded: Vec<&'a [u8]>,
+    /// A critical subpacket that is not understood, making the signature invalid.
+    unknown_critical: Option<u8>,
+    hash_prefix: [u8; 2],
+    /// The MPIs of the signature.
+    values: Vec<&'a [u8]>,
+}
+
+enum Issuer {
+    Fingerprint(Fingerprint),
+    KeyId([u8; 8]),
+}
+
+impl Signature<'_> {
+    /// Whether the signature may have been made by the key, as far as its issuer tells.
+    fn may_be_by(&self, key: &PublicKey) -> bool {
+        match &self.issuer {
+            Some(Issuer::Fingerprint(fingerprint)) => key.fingerprint == *fingerprint,
+            Some(Issuer::KeyId(key_id)) => key.fingerprint.key_id() == *key_id,
+            None => true,
+        }
+    }
+}
+
+/// A signature subpacket.
+struct Subpacket<'a> {
+    kind: u8,
+    /// Whether the signature is invalid if the subpacket is not understood.
+    critical: bool,
+    body: &'a [u8],
+}
+
+fn subpackets(mut input: &[u8]) -> Result<Vec<Subpacket<'_>>, ParseError> {
+    let mut subpackets = Vec::new();
+    while !input.is_empty() {
+        let len = match take_u8(&mut input)? {
+            first @ 0..=191 => usize::from(first),
+            first @ 192..=254 => {
+                ((usize::from(first) - 192) << 8) + usize::from(take_u8(&mut input)?) + 192
+            }
+            255 => take_u32(&mut input)?,
+        };
+        let mut body = take(&mut input, len)?;
+        let kind = take_u8(&mut body)?;
+        subpackets.push(Subpacket {
+            kind: kind & 0x7f,
+            critical: kind & 0x80 != 0,
+            body,
+        });
+    }
+    Ok(subpackets)
+}
+
+/// Find the issuer among signature subpackets.
+fn issuer(subpackets: &[Subpacket]) -> Option<Issuer> {
+    let mut issuer = None;
+    for subpacket in subpackets {
+        match (subpacket.kind, subpacket.body) {
+            (33, [4, fingerprint @ ..]) if fingerprint.len() == 20 => {
+                return Some(Issuer::Fingerprint(Fingerprint(
+                    fingerprint.try_into().unwrap(),
+                )));
+            }
+            (16, key_id) if key_id.len() == 8 => {
+                issuer = Some(Issuer::KeyId(key_id.try_into().unwrap()))
+            }
+            _ => {}
+        }
+    }
+    issuer
+}
+
+/// A time or period subpacket among hashed subpackets, zero standing for none.
+fn time(subpackets: &[Subpacket], kind: u8) -> Option<u64> {
+    let subpacket = subpackets.iter().find(|subpacket| subpacket.kind == kind)?;
+    let time = u32::from_be_bytes(subpacket.body.try_into().ok()?);
+    (time != 0).then_some(u64::from(time))
+}
+
+fn signature(body: &[u8]) -> Result<Option<Signature<'_>>, ParseError> {
+    let mut input = body;
+    if take_u8(&mut input)? != 4 {
+        return Ok(None);
+    }
+    let kind = take_u8(&mut input)?;
+    let key_algorithm = take_u8(&mut input)?;
+    let hash_algorithm = take_u8(&mut input)?;
+    let hashed_len = take_u16(&mut input)?;
+    let hashed_subpackets = subpackets(take(&mut input, hashed_len)?)?;
+    let hashed = &body[..6 + hashed_len];
+    let unhashed_len = take_u16(&mut input)?;
+    let unhashed_subpackets = subpackets(take(&mut input, unhashed_len)?)?;
+    let issuer = match issuer(&hashed_subpackets) {
+        Some(Issuer::Fingerprint(fingerprint)) => Some(Issuer::Fingerprint(fingerprint)),
+        hashed => issuer(&unhashed_subpackets).or(hashed),
+    };
+    // Only hashed subpackets are signed, but embedded signatures are signed themselves.
+    let all = || hashed_subpackets.iter().chain(&unhashed_subpackets);
+    let embedded = all()
+        .filter(|subpacket| subpacket.kind == 32)
+        .map(|subpacket| subpacket.body)
+        .collect();
+    let unknown_critical = all()
+        .find(|subpacket| subpacket.critical && !KNOWN_SUBPACKETS.contains(&subpacket.kind))
+        .map(|subpacket| subpacket.kind);
+    let hash_prefix = take(&mut input, 2)?.try_into().unwrap();
+    let values = match key_algorithm {
+        1..=3 => vec![take_mpi(&mut input)?],
+        22 => vec![take_mpi(&mut input)?, take_mpi(&mut input)?],
+        _ => return Ok(None),
+    };
+    Ok(Some(Signature {
+        kind,
+        hash_algorithm,
+        hashed,
+        issuer,
+        created: time(&hashed_subpackets, 2),
+        expires: time(&hashed_subpackets, 3),
+        key_expires: time(&hashed_subpackets, 9),
+        key_flags: hashed_subpackets
+            .iter()
+            .find(|subpacket| subpacket.kind == 27)
+            .and_then(|subpacket| subpacket.body.first().copied()),
+        embedded,
+        unknown_critical,
+        hash_prefix,
+        values,
+    }))
+}
+
+/// Hash data with the given algorithm, as used by RSA signatures.
+fn digest<D: Digest + rsa::pkcs8::AssociatedOid>(
+    data: &[u8],
+    signature: &Signature,
+) -> (Vec<u8>, Pkcs1v15Sign) {
+    let mut hasher = D::new();
+    hasher.update(data);
+    hasher.update(signature.hashed);
+    hasher.update([4, 0xff]);
+    hasher.update((signature.hashed.len() as u32).to_be_bytes());
+    (hasher.finalize().to_vec(), Pkcs1v15Sign::new::<D>())
+}
+
+impl PublicKey {
+    /// Check a signature by the key over data at the time `now`: besides the signature
+    /// itself, its subpackets must be understood and its creation and expiration times
+    /// valid.
+    fn check(&self, data: &[u8], signature: &Signature, now: u64) -> Result<(), String> {
+        if let Some(kind) = signature.unknown_critical {
+            return Err(format!("unknown critical subpacket {}", kind));
+        }
+        let Some(created) = signature.created else {
+            return Err("no signature creation time".to_string());
+        };
+        if created < self.created {
+            return Err("signature older than the key".to_string());
+        }
+        if signature
+            .expires
+            .is_some_and(|expires| created + expires <= now)
+        {
+            return Err("expired signature".to_string());
+        }
+        self.verify(data, signature)
+    }
+
+    /// Whether the key may still be used at the time `now`, being neither revoked nor
+    /// expired.
+    fn valid_at(&self, now: u64) -> Result<(), String> {
+        if self.revoked {
+            return Err("revoked key".to_string());
+        }
+        if self.expires.is_some_and(|expires| expires <= now) {
+            return Err("expired key".to_string());
+        }
+        Ok(())
+    }
+
+    fn verify(&self, data: &[u8], signature: &Signature) -> Result<(), String> {
+        let (hash, scheme) = match signature.hash_algorithm {
+            8 => digest::<Sha256>(data, signature),
+            9 => digest::<Sha384>(data, signature),
+            10 => digest::<Sha512>(data, signature),
+            11 => digest::<Sha224>(data, signature),
+            // MD5 and SHA-1 are refused, as apt does, and other algorithms unsupported.
+            algorithm => return Err(format!("unsupported hash algorithm {}", algorithm)),
+        };
+        if hash[..2] != signature.hash_prefix {
+            return Err("digest mismatch".to_string());
+        }
+        let verified = match (&self.material, &signature.values[..]) {
+            (KeyMaterial::Rsa(key), [value]) => {
+                // The signature is as long as the modulus, once leading zeros are restored.
+                let mut padded = vec![0; key.size().saturating_sub(value.len())];
+                padded.extend_from_slice(value);
+                key.verify(scheme, &hash, &padded).is_ok()
+            }
+            (KeyMaterial::Ed25519(key), [r, s]) if r.len() <= 32 && s.len() <= 32 => {
+                let mut bytes = [0; 64];
+                bytes[32 - r.len()..32].copy_from_slice(r);
+                bytes[64 - s.len()..].copy_from_slice(s);
+                let value = ed25519_dalek::Signature::from_bytes(&bytes);
+                key.verify(&hash, &value).is_ok()
+            }
+            _ => false,
+        };
+        match verified {
+            true => Ok(()),
+            false => Err("invalid signature".to_string()),
+        }
+    }
+}
+
+impl Keyring {
+    /// Verify the signatures of the given packets over data, returning the fingerprint of
+    /// the key of the first good one.
+    ///
+    /// As in apt, signatures by keys outside of the keyring are ignored, but there must be
+    /// at least one good signature and no bad signature by a key of the keyring. Signatures
+    /// by expired or revoked keys are bad.
+    fn verify(
+        &self,
+        data: &[u8],
+        text: &[u8],
+        signatures: &[u8],
+    ) -> Result<Fingerprint, ParseError> {
+        let now = now();
+        let mut good = None;
+        for packet in packets(signatures)? {
+            let Some(signature) = (packet.tag == 2)
+                .then(|| signature(packet.body))
+                .transpose()?
+                .flatten()
+            else {
+                continue;
+            };
+            let data = match signature.kind {
+                0x00 => data,
+                0x01 => text,
+                kind => {
+                    return Err(bad_signature(format!(
+                        "unexpected signature type {:#04x}",
+                        kind
+                    )))
+                }
+            };
+            let mut keys = self
+                .keys
+                .iter()
+                .filter(|key| signature.may_be_by(key))
+                .peekable();
+            if keys.peek().is_none() {
+                continue;
+            }
+            let mut errors = Vec::new();
+            match keys.find(|key| {
+                match key
+                    .check(data, &signature, now)
+                    .and_then(|()| key.valid_at(now))
+                {
+                    Ok(()) => true,
+                    Err(e) => {
+                        errors.push(format!("{}: {}", key.fingerprint, e));
+                        false
+                    }
+                }
+            }) {
+                Some(key) => {
+                    good.get_or_insert(key.fingerprint);
+                }
+                None if signature.issuer.is_none() => {}
+                None => return Err(bad_signature(errors.join(", "))),
+            }
+        }
+        good.ok_or_else(|| bad_signature("no signature by a key of the keyring".to_string()))
+    }
+
+    /// Verify a detached signature, such as `Release.gpg`, binary or ASCII-armored.
+    pub fn verify_detached(
+        &self,
+        data: &[u8],
+        signature: &[u8],
+    ) -> Result<Fingerprint, ParseError> {
+        let text = String::from_utf8_lossy(data)
+            .replace("\r\n", "\n")
+            .replace('\n', "\r\n");
+        self.verify(data, text.as_bytes(), &dearmor(signature)?)
+    }
+
+    /// Verify an OpenPGP cleartext signed message, such as an `InRelease` file, returning
+    /// its signed content.
+    ///
+    /// The message is read as by [`signed_content`](crate::deb822::signed_content), blank
+    /// lines before it included, but unlike it, which takes anything for content, text
+    /// outside of the signed part is refused.
+    pub fn verify_cleartext(&self, message: &str) -> Result<String, ParseError> {
+        let mut lines = message
+            .lines()
+            .map(|line| line.strip_suffix('\r').unwrap_or(line))
+            .skip_while(|line| line.trim().is_empty());
+        if lines.next() != Some(PGP_SIGNED_MESSAGE) {
+            return Err(bad_signature("not an OpenPGP signed message".to_string()));
+        }
+        // Skip the armor headers, which end at the first empty line.
+        lines.by_ref().find(|line| line.trim().is_empty());
+        let mut content = String::new();
+        let mut text = Vec::new();
+        let mut signed = false;
+        for line in lines.by_ref() {
+            if line == PGP_SIGNATURE {
+                signed = true;
+                break;
+            }
+            let line = line.strip_prefix("- ").unwrap_or(line);
+            content.push_str(line);
+            content.push('\n');
+            // Trailing whitespace is not signed, and neither is the last line ending.
+            text.push(line.trim_end_matches([' ', '\t']));
+        }
+        let signature: Vec<&str> = lines.collect();
+        let end = signature
+            .iter()
+            .position(|line| *line == "-----END PGP SIGNATURE-----");
+        if !signed
+            || end.is_none_or(|end| {
+                signature[end + 1..]
+                    .iter()
+                    .any(|line| !line.trim().is_empty())
+            })
+        {
+            return Err(bad_signature(
+                "text outside of the signed message".to_string(),
+            ));
+        }
+        let armored = format!("{}\n{}", PGP_SIGNATURE, signature.join("\n"));
+        let text = text.join("\r\n");
+        self.verify(
+            text.as_bytes(),
+            text.as_bytes(),
+            &dearmor(armored.as_bytes())?,
+        )?;
+        Ok(content)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::deb822::signed_content;
+    use crate::debian_version::DebianVersion;
+    use crate::error::{Diagnostics, Mode};
+    use crate::index::Index;
+    use crate::release::load_signed_mirror;
+    use crate::sources::{load_lists, parse_sources_list};
+
+    /// An Ed25519 key and an RSA key, exported by gpg.
+    const KEYS: &str = r#"-----BEGIN PGP PUBLIC KEY BLOCK-----
+
+mDMEatKGERYJKwYBBAHaRw8BAQdAKkHcQtMQyy8r59iSE9N9sawlHsGJrZMNkVTQ
+I1FMp+K0JlRlc3QgQXJjaGl2ZSBLZXkgPGFyY2hpdmVAZXhhbXBsZS5vcmc+iJAE
+ExYIADgWIQQ8cbZFT89TfxY96CdRrco87BVObAUCatKGEQIbAwULCQgHAgYVCgkI
+CwIEFgIDAQIeAQIXgAAKCRBRrco87BVObO2AAQDLhS+QWLScj92ciBh+G5YDsdxF
+CpYmp/P0E0IiD8wCnQEAlmFJNq1p5/miIHeRAK4qFUw9+PF/nKrFN2P/iCv8aQ2Z
+AQ0EatKGEQEIAJ3rhvnZdNR6pE1VEMTcX9TChBCoRIw4tLifaYY6TvUCAA3UgjrF
+30O+kwrpzj66jQS2Yrs8Y7kWDmjqubPkbLbQo5UnNbouCawbamlzB6l/qW2Kfp1+
+O/tdCXs985QmSwnzKkVj3DHAleqZClwwn4dkS3gXJ4SahJ4rR/u8wBU9olwW5K0w
+yymWOGjtB3fJ1STA8FMlbgjlVyHzzjhFZTPNq8apNad5oK5iRCUznNkIbZ2ODC3t
+fPVSJ40tguRqGy+co1KUk8ldmRULXbEjb5zDfsWSPzmY7glCJFZEnK74pvrcCS+G
+MfBtYM9hS/Fha7on0LziDUltdqfeEh/Aw5cAEQEAAbQeVGVzdCBSU0EgS2V5IDxy
+c2FAZXhhbXBsZS5vcmc+iQFOBBMBCgA4FiEEYOzzi0mlcN7Jhq2zedBkyIyB1sUF
+AmrShhECGwMFCwkIBwIGFQoJCAsCBBYCAwECHgECF4AACgkQedBkyIyB1sXVnAgA
+gEJwbVkwq4GxaUXroXqvUAJTXNnXzCqrY7/xV4xP75PeGdCrLyf23L7Jbkmvyg46
+KP8F5TE5yh4AOYXl3oedx2iIGJPJlEdjYETLOtsT/ZZNA+ESYHHD60PVaQKcu6mm
+ypRKLrn9zZo4MAosTn1A7/+igaY7WqD54+D/Tp7Mj5djiGsOxYTb+8dK+pMrx/eK
+GnUPeSoBdxfOZhaMqSrsbrDJWS1D8TG3LdczuBeLZ8OjsAn2y7bZodTbZre3nE+B
+AjtgaR2/LArXnsAaOHXAwQ33s9UKomT2KS6hr2Fm0QYND7jyvhlRetUmc4wL/mVh
+LTfWSRfmGsL/qfaO0nCQ7A==
+=JrNz
+-----END PGP PUBLIC KEY BLOCK-----
+"#;
+
+    /// Signed by both keys. The trailing whitespace of the Suite field is not signed.
+    const IN_RELEASE: &str = r#"-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA256,SHA512
+
+Origin: Test
+Suite: stable  
+Components: main
+Date: Sat, 10 Feb 2024 09:37:03 UTC
+SHA256:
+ 50ab596bd22530812dc0ded894228c81ec55b077f1fe85f4224fd1cbf0c71bb4       51 main/binary-amd64/Packages
+-----BEGIN PGP SIGNATURE-----
+
+iIoEARYIADIWIQQ8cbZFT89TfxY96CdRrco87BVObAUCatKGVxQcYXJjaGl2ZUBl
+eGFtcGxlLm9yZwAKCRBRrco87BVObAdFAQDEgVOmLBpviEGxiv1dQ2OR1Ik2b8Ki
+y+QAXEmin+MEkgD/VZJ8iD/x4KkjfLrliAa1PV8p2AZU89v67eWFNBC9FA6JAUQE
+AQEKAC4WIQRg7POLSaVw3smGrbN50GTIjIHWxQUCatKGVxAccnNhQGV4YW1wbGUu
+b3JnAAoJEHnQZMiMgdbFSMYH/2/SWZCzq8DxFPz0zRaMvV2JOdPnCUJgclCuG12s
+qmUWecCbkyJR1aYp0f474VGrD6B+yfa1+w27Epdx7+BkrBQlKehZ2hhZRxU+Hy8H
+k0RQ6XqaVBhX5LUwKrj7T6plWtnoCnOFxIGe8XGZ5e4FFSmLiTs5kGGEFUlIwq63
+WCp2I6EZJACCeL3sMKp4VJ0OwODKB3JJlx49aawk3i69cZDUOx1ifOiwgiPKymVJ
+fQIChHkuFVUvBCpWv7aNM1yFx/VRhFXeXSnMOX6+K4gs5vw6UfFI85QpcaoEnBa9
+6IinECbVad/lTXmrJWf//al8VUPzMxyYKl1cURB25QdrfEQ=
+=QJtY
+-----END PGP SIGNATURE-----
+"#;
+
+    const RELEASE: &str = r#"Origin: Test
+Suite: stable  
+Components: main
+Date: Sat, 10 Feb 2024 09:37:03 UTC
+SHA256:
+ 50ab596bd22530812dc0ded894228c81ec55b077f1fe85f4224fd1cbf0c71bb4       51 main/binary-amd64/Packages
+"#;
+
+    /// Signed by the RSA key.
+    const RELEASE_GPG: &str = r#"-----BEGIN PGP SIGNATURE-----
+
+iQFEBAABCgAuFiEEYOzzi0mlcN7Jhq2zedBkyIyB1sUFAmrShlcQHHJzYUBleGFt
+cGxlLm9yZwAKCRB50GTIjIHWxazKCACRUXV2z1Or33jeJlFGrnGjz5iGZy9Px8w+
+u/587U7jLedder2gM09anqsRn9iYu53jhfGHWUL6RKlUKehFFtglu1pxQ0+Kd5Zm
+ntEya7j7V1TTJmc4Gqe/017MCtuGuu+bnu/gnBChxWoAZl5hh/3kNbnsp5OfQCds
+JNxnTsoISD94oBR6rJJ9BAWYr1w5KJMBtdIpxib7qbUnhsXQxOtH20Ff7v73IGor
+3rFldaKqtxFinG3xFXk+f2bUBtsPE0ROESXOCVt2nawyjNsRwOntUfmTw3QwdMiM
+OfQ/N444428U6y08bhf9ehZltKaFCPZQyAY+QPyBMLaXIc7jN+UW
+=U2QX
+-----END PGP SIGNATURE-----
+"#;
+
+    /// Signed by the Ed25519 key, past its Valid-Until date.
+    const EXPIRED_IN_RELEASE: &str = r#"-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA256
+
+Origin: Test
+Suite: stable
+Components: main
+Date: Sat, 10 Feb 2024 09:37:03 UTC
+Valid-Until: Sat, 17 Feb 2024 09:37:03 UTC
+SHA256:
+ 50ab596bd22530812dc0ded894228c81ec55b077f1fe85f4224fd1cbf0c71bb4       51 main/binary-amd64/Packages
+-----BEGIN PGP SIGNATURE-----
+
+iHUEARYIAB0WIQQ8cbZFT89TfxY96CdRrco87BVObAUCatKKxgAKCRBRrco87BVO
+bNaEAP95cy1vw5EGYKyA/at4Sgde0Ed68zT+mbpUh9KOhBeaoAD9FIcduZPrTgOd
+5HRBUrVvMTGba0P6I5LxEVkLWme0rAA=
+=Ddxr
+-----END PGP SIGNATURE-----
+"#;
+
+    const ED25519: &str = "3C71B6454FCF537F163DE82751ADCA3CEC154E6C";
+    const RSA: &str = "60ECF38B49A570DEC986ADB379D064C88C81D6C5";
+
+    /// A key that expired at the end of 2020, a revoked key, and a certification-only key
+    /// with a signing subkey, exported by gpg.
+    const OTHER_KEYS: &str = r#"-----BEGIN PGP PUBLIC KEY BLOCK-----
+
+mDMEXgvhABYJKwYBBAHaRw8BAQdAfKJtnVfaO9LGS/ZAs2NjDXUa689N0LHXq5an
+yczHC+20IUV4cGlyZWQgS2V5IDxleHBpcmVkQGV4YW1wbGUub3JnPoiWBBMWCAA+
+FiEEnEIYj+0AvCOSrKBwPN+EB07Wg5YFAl4L4QACGwMFCQHhM4AFCwkIBwIGFQoJ
+CAsCBBYCAwECHgECF4AACgkQPN+EB07Wg5bEzQD/WBgnpPuVDyl6zpR4zr40eZR0
+tvxpcts1gEqMrcdFKHUBAKvtLHrKmgpGt/TiFORMqZR7N6jf21TJNiz9nvUZR2kO
+mDMEatKPLRYJKwYBBAHaRw8BAQdAh2Qxik3f5AMnc0dySablBPKeYBz+DKD8wFMf
+IYPpaWeIeAQgFggAIBYhBE5Ds2hNbujljS6tGAZtQrYPrF9DBQJq0o8uAh0AAAoJ
+EAZtQrYPrF9DymYBAKNTTSJJ1PtN6RCI2QwjgHSn/OQ4+4+ZUm05h5YvOnvkAQDJ
+Qle9B/HnkSUs0wzCWa8Ke6Ve3rkAQFsUC5yuLL4hBLQhUmV2b2tlZCBLZXkgPHJl
+dm9rZWRAZXhhbXBsZS5vcmc+iJAEExYIADgWIQROQ7NoTW7o5Y0urRgGbUK2D6xf
+QwUCatKPLQIbAwULCQgHAgYVCgkICwIEFgIDAQIeAQIXgAAKCRAGbUK2D6xfQxG0
+AQDlX1DnbY7H7LvHZwdqx+B/ODzkdZl9aUEudHe8F9VYeQD/XPvMTgqIDIZIlFbw
+XxnuSLfgtRCRM1XdZtl2rcaAFQeYMwRq0o8vFgkrBgEEAdpHDwEBB0AmiW1gMvO7
+HiToBo1ihbWsIG0mQIz4x1/ivrU/mV6e57QfU3Via2V5IEtleSA8c3Via2V5QGV4
+YW1wbGUub3JnPoiQBBMWCAA4FiEE0UUxjsQKhT3QZo3KXpWGw82RPrIFAmrSjy8C
+GwEFCwkIBwIGFQoJCAsCBBYCAwECHgECF4AACgkQXpWGw82RPrJgtgEA/1OlGWVu
+IJ2BLB00oMiQjVsiRyXP+8b/45Id0adTOBYBANBdD2OWMjtVE/MtCJjfkGSslsJY
+itOVBt786JqDwJgDuDMEatKPNhYJKwYBBAHaRw8BAQdA4KJhYI+PlJx5aPFtI43G
+jDrwzb33lXiJHWmqcDW4iOqI7wQYFggAIBYhBNFFMY7ECoU90GaNyl6VhsPNkT6y
+BQJq0o82AhsCAIEJEF6VhsPNkT6ydiAEGRYIAB0WIQRlV5EAgGkgAbRZRS7ee6sy
+C44ysAUCatKPNgAKCRDee6syC44ysM9lAP4ttHVme3kfG13gf2jNumD5Qd4l3x+w
+od+e8eibsYLgagD+PpyWARsJCG/s4lWbAL6R0fKNI8f4N1QH6Xo27gJ1rwba1gEA
+ro9sU5Hwu7gIXkPZtpHprBgFaSif54abRIXzujQxTb8A/0qpx7lWZc8wK/Np0jn1
+4H39QZhV5luAPdXyVrjJF80P
+=eK5N
+-----END PGP PUBLIC KEY BLOCK-----
+"#;
+
+    const EXPIRED: &str = "9C42188FED00BC2392ACA0703CDF84074ED68396";
+    const REVOKED: &str = "4E43B3684D6EE8E58D2EAD18066D42B60FAC5F43";
+    const CERTIFICATION: &str = "D145318EC40A853DD0668DCA5E9586C3CD913EB2";
+    const SUBKEY: &str = "6557910080692001B459452EDE7BAB320B8E32B0";
+
+    /// Signatures of `RELEASE` by the expired key, made before it expired, by the revoked
+    /// key and by the subkey.
+    const EXPIRED_KEY_GPG: &str = r#"-----BEGIN PGP SIGNATURE-----
+
+iHUEABYIAB0WIQScQhiP7QC8I5KsoHA834QHTtaDlgUCXtRFAAAKCRA834QHTtaD
+lruGAP9BYbVwxLWbpXRnq8iPj82g3KcKCr/+ekCGJ+sLpyx52QEAtTyU3Xzr9D9k
+FrOyG7qmIIlQoJ7sqvOgd06AeoNJYQs=
+=VsEk
+-----END PGP SIGNATURE-----
+"#;
+    const REVOKED_KEY_GPG: &str = r#"-----BEGIN PGP SIGNATURE-----
+
+iHUEABYIAB0WIQROQ7NoTW7o5Y0urRgGbUK2D6xfQwUCatKPOQAKCRAGbUK2D6xf
+Q7qhAQCDDDsqFSifqiSmjegL11J45VhCUbWyRSJPltLK/T279gEAwZYKbRD53L2L
+xXhAPTYSSHR0DOULsxD4x3ahTUqZvwc=
+=cp2r
+-----END PGP SIGNATURE-----
+"#;
+    const SUBKEY_GPG: &str = r#"-----BEGIN PGP SIGNATURE-----
+
+iHUEABYIAB0WIQRlV5EAgGkgAbRZRS7ee6syC44ysAUCatKPOQAKCRDee6syC44y
+sEoeAQCQiTbcdwqJj1dt9EAWVcVVzyFcndfDCi7wyEDaVyzrjQD8DRF+M/yA0vr+
+I0tjWKBEkjLSrnI4JXZxRuSjd/zhRAE=
+=U14D
+-----END PGP SIGNATURE-----
+"#;
+
+    /// Signatures of `RELEASE` by the Ed25519 key: expiring a second after their creation,
+    /// dated before the creation of the key, and with a critical notation.
+    const EXPIRED_GPG: &str = r#"-----BEGIN PGP SIGNATURE-----
+
+iHsEABYIACMWIQQ8cbZFT89TfxY96CdRrco87BVObAUCatKPSgWDAAAAAQAKCRBR
+rco87BVObHAxAQDSYiGZ8eFQfqadJJqu1Q+t2xTjs8tRTFNP6CIe5ETPfAEAhihV
+2BFz1jW7Pfz0FIveOr1OfqO70HF3xgzoHj0BMQY=
+=IYRx
+-----END PGP SIGNATURE-----
+"#;
+    const EARLY_GPG: &str = r#"-----BEGIN PGP SIGNATURE-----
+
+iHUEABYIAB0WIQQ8cbZFT89TfxY96CdRrco87BVObAUCXgvhAAAKCRBRrco87BVO
+bC1rAQCHQ4cyZUjFQjGkkQ47DAylur/UGaS2iW0nHf7nmmkiBgD/feiJLGh2U/cH
+2fW6I8qiFpoPHMrSzp3+pszQ8gpk1Ak=
+=mAJJ
+-----END PGP SIGNATURE-----
+"#;
+    const CRITICAL_GPG: &str = r#"-----BEGIN PGP SIGNATURE-----
+
+iJYEABYIAD4WIQQ8cbZFT89TfxY96CdRrco87BVObAUCatKPPyCUgAAAAAAUAANj
+cml0aWNhbEBleGFtcGxlLm9yZ3llcwAKCRBRrco87BVObD7bAP47V+KOYu/OD6iH
+ZcGtEgBCV2JBfXmFxdvWkURabgpVagD/Vixf+qTDG3haRUtbWlX+xIAG+lrAhHUc
+oyBAmTBOtAA=
+=vXE1
+-----END PGP SIGNATURE-----
+"#;
+
+    fn is_bad_signature(result: Result<impl fmt::Debug, ParseError>) -> bool {
+        matches!(result.unwrap_err().kind, ParseErrorKind::BadSignature(_))
+    }
+
+    fn bad_signature_message(result: Result<impl fmt::Debug, ParseError>) -> String {
+        match result.unwrap_err().kind {
+            ParseErrorKind::BadSignature(message) => message,
+            kind => panic!("not a bad signature: {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_parse_keyring() -> Result<(), Box<dyn Error>> {
+        let keyring = parse_keyring(KEYS.as_bytes())?;
+        let fingerprints: Vec<String> = keyring
+            .keys
+            .iter()
+            .map(|key| key.fingerprint.to_string())
+            .collect();
+        assert_eq!(fingerprints, [ED25519, RSA]);
+        assert!(parse_keyring(b"-----BEGIN PGP PUBLIC KEY BLOCK-----\n\n!!\n").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_certificates() -> Result<(), Box<dyn Error>> {
+        let keyring = parse_keyring(OTHER_KEYS.as_bytes())?;
+        let keys: Vec<(String, String)> = keyring
+            .keys
+            .iter()
+            .map(|key| (key.fingerprint.to_string(), key.primary.to_string()))
+            .collect();
+        // The certification-only primary key is skipped, but not its signing subkey.
+        assert_eq!(
+            keys,
+            [
+                (EXPIRED.to_string(), EXPIRED.to_string()),
+                (REVOKED.to_string(), REVOKED.to_string()),
+                (SUBKEY.to_string(), CERTIFICATION.to_string()),
+            ]
+        );
+        assert_eq!(keyring.keys[0].expires, Some(1609372800));
+        assert!(!keyring.keys[0].revoked && keyring.keys[1].revoked);
+        assert_eq!(keyring.keys[2].expires, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_unbound_subkey() -> Result<(), Box<dyn Error>> {
+        let keyring = parse_keyring(OTHER_KEYS.as_bytes())?;
+        let signer = keyring.verify_detached(RELEASE.as_bytes(), SUBKEY_GPG.as_bytes())?;
+        assert_eq!(signer.to_string(), SUBKEY);
+
+        // Without its binding signature, the subkey is not part of the certificate.
+        let data = dearmor(OTHER_KEYS.as_bytes())?;
+        let mut unbound = Vec::new();
+        for packet in packets(&data)? {
+            if packet.tag == 2 && packet.body[1] == 0x18 {
+                continue;
+            }
+            unbound.extend([0xc0 | packet.tag, 0xff]);
+            unbound.extend((packet.body.len() as u32).to_be_bytes());
+            unbound.extend(packet.body);
+        }
+        let keyring = parse_keyring(&unbound)?;
+        assert!(keyring
+            .keys
+            .iter()
+            .all(|key| key.fingerprint.to_string() != SUBKEY));
+        assert!(is_bad_signature(
+            keyring.verify_detached(RELEASE.as_bytes(), SUBKEY_GPG.as_bytes())
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_expired_and_revoked_keys() -> Result<(), Box<dyn Error>> {
+        let keyring = parse_keyring(OTHER_KEYS.as_bytes())?;
+        let expired = keyring.verify_detached(RELEASE.as_bytes(), EXPIRED_KEY_GPG.as_bytes());
+        assert_eq!(
+            bad_signature_message(expired),
+            format!("{}: expired key", EXPIRED)
+        );
+        let revoked = keyring.verify_detached(RELEASE.as_bytes(), REVOKED_KEY_GPG.as_bytes());
+        assert_eq!(
+            bad_signature_message(revoked),
+            format!("{}: revoked key", REVOKED)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_signature_times() -> Result<(), Box<dyn Error>> {
+        let keyring = parse_keyring(KEYS.as_bytes())?;
+        let expired = keyring.verify_detached(RELEASE.as_bytes(), EXPIRED_GPG.as_bytes());
+        assert_eq!(
+            bad_signature_message(expired),
+            format!("{}: expired signature", ED25519)
+        );
+        let early = keyring.verify_detached(RELEASE.as_bytes(), EARLY_GPG.as_bytes());
+        assert_eq!(
+            bad_signature_message(early),
+            format!("{}: signature older than the key", ED25519)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_critical_subpacket() -> Result<(), Box<dyn Error>> {
+        let keyring = parse_keyring(KEYS.as_bytes())?;
+        let critical = keyring.verify_detached(RELEASE.as_bytes(), CRITICAL_GPG.as_bytes());
+        assert_eq!(
+            bad_signature_message(critical),
+            format!("{}: unknown critical subpacket 20", ED25519)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_cleartext() -> Result<(), Box<dyn Error>> {
+        let keyring = parse_keyring(KEYS.as_bytes())?;
+        assert_eq!(keyring.verify_cleartext(IN_RELEASE)?, RELEASE);
+        assert_eq!(
+            keyring.verify_cleartext(&IN_RELEASE.replace('\n', "\r\n"))?,
+            RELEASE
+        );
+
+        // Signatures by other keys are ignored.
+        let rsa = Keyring {
+            keys: keyring.keys[1..].to_vec(),
+        };
+        assert_eq!(rsa.verify_cleartext(IN_RELEASE)?, RELEASE);
+        let ed25519 = Keyring {
+            keys: keyring.keys[..1].to_vec(),
+        };
+        assert_eq!(ed25519.verify_cleartext(IN_RELEASE)?, RELEASE);
+        assert!(is_bad_signature(
+            Keyring::default().verify_cleartext(IN_RELEASE)
+        ));
+        assert!(is_bad_signature(keyring.verify_cleartext(RELEASE)));
+
+        let tampered = IN_RELEASE.replace("Suite: stable", "Suite: unstable");
+        assert!(is_bad_signature(rsa.verify_cleartext(&tampered)));
+        let appended = format!("{}Suite: unstable\n", IN_RELEASE);
+        assert!(is_bad_signature(keyring.verify_cleartext(&appended)));
+
+        // Leading blank lines are skipped, as when reading the message without verifying it.
+        let indented = format!("\n \r\n{}", IN_RELEASE);
+        assert_eq!(keyring.verify_cleartext(&indented)?, RELEASE);
+        assert_eq!(signed_content(&indented), RELEASE);
+        let prefixed = format!("Suite: unstable\n{}", IN_RELEASE);
+        assert!(is_bad_signature(keyring.verify_cleartext(&prefixed)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_detached() -> Result<(), Box<dyn Error>> {
+        let keyring = parse_keyring(KEYS.as_bytes())?;
+        let signer = keyring.verify_detached(RELEASE.as_bytes(), RELEASE_GPG.as_bytes())?;
+        assert_eq!(signer.to_string(), RSA);
+        let tampered = RELEASE.replace("stable", "unstable");
+        assert!(is_bad_signature(
+            keyring.verify_detached(tampered.as_bytes(), RELEASE_GPG.as_bytes())
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_signed_mirror() -> Result<(), Box<dyn Error>> {
+        let tmp = tempfile::tempdir()?;
+        let dist = tmp.path();
+        let keyring = parse_keyring(KEYS.as_bytes())?;
+        let load = || {
+            load_signed_mirror(
+                &mut Index::new(),
+                dist,
+                &keyring,
+                &mut Diagnostics::default(),
+            )
+        };
+        fs::create_dir_all(dist.join("main/binary-amd64"))?;
+        fs::write(
+            dist.join("main/binary-amd64/Packages"),
+            "Package: hello\nVersion: 2.10-3\nArchitecture: amd64\n",
+        )?;
+        fs::write(dist.join("InRelease"), IN_RELEASE)?;
+        let mut index = Index::new();
+        let in_release =
+            load_signed_mirror(&mut index, dist, &keyring, &mut Diagnostics::default())?;
+        assert_eq!(in_release.suite, "stable");
+        assert_eq!(
+            index.available_versions(&"hello".to_string()),
+            [DebianVersion("2.10-3".to_string())]
+        );
+
+        fs::write(
+            dist.join("InRelease"),
+            IN_RELEASE.replace("Suite: stable", "Suite: unstable"),
+        )?;
+        assert!(is_bad_signature(load()));
+
+        fs::write(dist.join("InRelease"), EXPIRED_IN_RELEASE)?;
+        let expired = load_signed_mirror(
+            &mut Index::new(),
+            dist,
+            &keyring,
+            &mut Diagnostics::new(Mode::Lenient),
+        );
+        assert!(matches!(
+            expired.unwrap_err().kind,
+            ParseErrorKind::Expired(_)
+        ));
+
+        fs::remove_file(dist.join("InRelease"))?;
+        fs::write(dist.join("Release"), RELEASE)?;
+        assert!(matches!(load().unwrap_err().kind, ParseErrorKind::Io(_)));
+        fs::write(dist.join("Release.gpg"), RELEASE_GPG)?;
+        assert_eq!(load()?.suite, "stable");
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_signed_lists() -> Result<(), Box<dyn Error>> {
+        let tmp = tempfile::tempdir()?;
+        let lists = tmp.path();
+        let keyring = parse_keyring(KEYS.as_bytes())?;
+        let list = |name: &str| lists.join(format!("deb.debian.org_debian_dists_stable_{}", name));
+        let packages = "Package: hello\nVersion: 2.10-3\nArchitecture: amd64\n";
+        let entries = parse_sources_list(
+            "deb http://deb.debian.org/debian stable main\n",
+            &mut Diagnostics::default(),
+        )?;
+        let load = || {
+            load_lists(
+                &mut Index::new(),
+                &entries,
+                lists,
+                Some(&keyring),
+                &mut Diagnostics::new(Mode::Lenient),
+            )
+        };
+        fs::write(list("InRelease"), IN_RELEASE)?;
+        // As stored by apt with Acquire::GzipIndexes, the Release file only listing the
+        // uncompressed file.
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut gz, packages.as_bytes())?;
+        fs::write(list("main_binary-amd64_Packages.gz"), gz.finish()?)?;
+        load()?;
+        fs::remove_file(list("main_binary-amd64_Packages.gz"))?;
+        fs::write(
+            list("main_binary-amd64_Packages"),
+            packages.replace("2.10-3", "2.10-4"),
+        )?;
+        let tampered = load();
+        assert!(matches!(
+            tampered.unwrap_err().kind,
+            ParseErrorKind::ChecksumMismatch(_)
+        ));
+        let contrib = parse_sources_list(
+            "deb http://deb.debian.org/debian stable contrib\n",
+            &mut Diagnostics::default(),
+        )?;
+        fs::write(list("contrib_binary-amd64_Packages"), packages)?;
+        let unlisted = load_lists(
+            &mut Index::new(),
+            &contrib,
+            lists,
+            Some(&keyring),
+            &mut Diagnostics::default(),
+        );
+        assert!(matches!(
+            unlisted.unwrap_err().kind,
+            ParseErrorKind::ChecksumMismatch(_)
+        ));
+        Ok(())
+    }
+}
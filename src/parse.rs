@@ -1,21 +1,41 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
 use std::fs;
+use std::io::{BufRead, BufReader, Read};
 use std::path::Path;
 use std::str::FromStr;
 
+use memmap2::Mmap;
 use pubgrub::Range;
 
+use crate::arch;
+use crate::compression::{self, Compression};
+use crate::deb822::{
+    paragraphs, parse_paragraphs, FieldRef, Paragraph, ParagraphReader, ParagraphRef,
+};
 use crate::debian_version::DebianVersion;
+use crate::error::{Diagnostics, ParseError, ParseErrorKind};
 use crate::index;
-use crate::index::{HashedRange, Index};
+use crate::index::{HashedRange, Index, MultiArch, Origin, Priority};
+use crate::profile;
+use crate::profile::{ProfileTerm, RestrictionFormula};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct DebianPackage {
     pub package: String,
     pub version: String,
     pub depends: Vec<Dependency>,
+    pub pre_depends: Vec<Dependency>,
+    pub recommends: Vec<Dependency>,
+    pub suggests: Vec<Dependency>,
+    pub enhances: Vec<Dependency>,
     pub provides: Vec<Dependency>,
+    pub conflicts: Vec<Dependency>,
+    pub breaks: Vec<Dependency>,
+    pub architecture: Option<String>,
+    pub multi_arch: Option<String>,
+    pub essential: bool,
+    pub priority: Option<String>,
 }
 
 /// A dependency item is a list of alternatives (separated by the '|' symbol).
@@ -27,8 +47,12 @@ pub struct Dependency {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Alternative {
     pub package: String,
+    /// Architecture qualifier following the package name, e.g. `any` in `python3:any`.
+    pub arch_qualifier: Option<String>,
     pub version_constraint: Option<VersionConstraint>,
     pub arch: Option<Vec<String>>,
+    /// Build profile restriction formula, empty if unrestricted.
+    pub profiles: RestrictionFormula,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -63,7 +87,7 @@ impl FromStr for VersionRelation {
 /// Parse a version constraint string (e.g. ">= 2.2.1") into a VersionConstraint.
 fn parse_version_constraint(s: &str) -> Result<VersionConstraint, Box<dyn Error>> {
     // Split on whitespace; expect two parts: the relation and the version.
-    let parts: Vec<&str> = s.trim().split_whitespace().collect();
+    let parts: Vec<&str> = s.split_whitespace().collect();
     if parts.len() < 2 {
         return Err(format!("Invalid version constraint: {}", s).into());
     }
@@ -77,6 +101,9 @@ fn parse_version_constraint(s: &str) -> Result<VersionConstraint, Box<dyn Error>
 ///   "libc6 (>= 2.2.1)"
 ///   "libqt5core5a (>= 5.7.0) [amd64 i386]"
 ///   "default-mta"
+///   "python3:any (>= 3.7)"
+///   "libseccomp-dev [!hurd-any]"
+///   "python3-pytest <!nocheck>"
 fn parse_alternative(s: &str) -> Result<Alternative, Box<dyn Error>> {
     let s = s.trim();
     // Look for a version constraint: find the first '('.
@@ -90,7 +117,8 @@ fn parse_alternative(s: &str) -> Result<Alternative, Box<dyn Error>> {
         let after = s[end + 1..].trim();
         (pkg, Some((constraint_str, after)))
     } else {
-        (s, None)
+        // Without a version constraint the name ends at any restriction list.
+        (s.split(['[', '<']).next().unwrap_or(s).trim(), None)
     };
 
     let version_constraint = if let Some((constraint_str, _)) = remainder {
@@ -139,13 +167,47 @@ fn parse_alternative(s: &str) -> Result<Alternative, Box<dyn Error>> {
         }
     };
 
+    // Look for build profile restrictions in angle brackets, if present.
+    let profiles = match remainder {
+        Some((_, after)) => parse_profiles(after)?,
+        None => parse_profiles(&s[pkg_part.len()..])?,
+    };
+
+    let (package, arch_qualifier) = match pkg_part.split_once(':') {
+        Some((package, qualifier)) => (package, Some(qualifier.to_string())),
+        None => (pkg_part, None),
+    };
+
     Ok(Alternative {
-        package: pkg_part.to_string(),
+        package: package.to_string(),
+        arch_qualifier,
         version_constraint,
         arch,
+        profiles,
     })
 }
 
+/// Parse the build profile restriction formula at the end of an alternative,
+/// e.g. "<!nocheck> <stage1 cross>".
+fn parse_profiles(s: &str) -> Result<RestrictionFormula, Box<dyn Error>> {
+    let mut formula = Vec::new();
+    let mut rest = s;
+    while let Some(start) = rest.find('<') {
+        let end = start
+            + rest[start..]
+                .find('>')
+                .ok_or("Missing closing angle bracket for build profile restrictions")?;
+        formula.push(
+            rest[start + 1..end]
+                .split_whitespace()
+                .map(ProfileTerm::from)
+                .collect(),
+        );
+        rest = &rest[end + 1..];
+    }
+    Ok(formula)
+}
+
 /// Parse a dependency item (which may contain alternatives separated by '|')
 fn parse_dependency_item(s: &str) -> Result<Dependency, Box<dyn Error>> {
     let alternatives: Result<Vec<Alternative>, Box<dyn Error>> =
@@ -155,94 +217,241 @@ fn parse_dependency_item(s: &str) -> Result<Dependency, Box<dyn Error>> {
     })
 }
 
+/// Parse the items of a relationship field, along with the byte offset of each item.
+fn parse_relations(
+    s: &str,
+) -> impl Iterator<Item = (usize, &str, Result<Dependency, Box<dyn Error>>)> {
+    let mut offset = 0;
+    s.split(',').filter_map(move |dep_str| {
+        let start = offset + dep_str.len() - dep_str.trim_start().len();
+        offset += dep_str.len() + 1;
+        let trimmed = dep_str.trim();
+        if trimmed.is_empty() {
+            None
+        } else {
+            Some((start, trimmed, parse_dependency_item(trimmed)))
+        }
+    })
+}
+
+fn invalid_relation(item: &str, e: Box<dyn Error>) -> ParseError {
+    ParseError::new(ParseErrorKind::InvalidRelation {
+        item: item.to_string(),
+        reason: e,
+    })
+}
+
 /// Parse the entire Depends field (a comma-separated list of dependency items)
-fn parse_dependency_field(s: &str) -> Vec<Dependency> {
-    let dependencies: Vec<Dependency> = s
-        .split(',')
-        .filter_map(|dep_str| {
-            let trimmed = dep_str.trim();
-            if trimmed.is_empty() {
-                None
-            } else {
-                match parse_dependency_item(trimmed) {
-                    Ok(dep) => Some(dep),
-                    Err(e) => {
-                        eprintln!("Error parsing dependency '{}': {}", trimmed, e);
-                        None
-                    }
-                }
+pub(crate) fn parse_dependency_field(s: &str) -> Result<Vec<Dependency>, ParseError> {
+    parse_relations(s)
+        .map(|(offset, item, dep)| dep.map_err(|e| invalid_relation(item, e).at(1, offset + 1)))
+        .collect()
+}
+
+/// Parse a relationship field of a paragraph, reporting malformed items at their position.
+/// In lenient mode they are skipped.
+fn paragraph_relations(
+    paragraph: &ParagraphRef,
+    stanza: usize,
+    name: &str,
+    diagnostics: &mut Diagnostics,
+) -> Result<Vec<Dependency>, ParseError> {
+    let Some(field) = paragraph
+        .fields()
+        .iter()
+        .find(|field| field.name.eq_ignore_ascii_case(name))
+    else {
+        return Ok(Vec::new());
+    };
+    let value = paragraph.get_folded(name).unwrap_or_default();
+    let mut dependencies = Vec::new();
+    for (offset, item, dep) in parse_relations(&value) {
+        match dep {
+            Ok(dep) => dependencies.push(dep),
+            Err(e) => {
+                let (line, column) = position(field, offset);
+                diagnostics.report(
+                    invalid_relation(item, e)
+                        .in_stanza(stanza)
+                        .in_field(field.name)
+                        .at(line, column),
+                )?;
             }
-        })
-        .collect();
-    dependencies
+        }
+    }
+    Ok(dependencies)
 }
 
-/// Parse a single control file stanza into a DebianPackage.
-/// This simplified parser assumes that each field is "Field: value" on a single line
-/// (with simple support for continuation lines).
-pub fn parse_debian_package(stanza: &str) -> Result<DebianPackage, Box<dyn Error>> {
-    let mut fields: HashMap<String, String> = HashMap::new();
-    let mut current_key: Option<String> = None;
-    let mut current_value = String::new();
-
-    for line in stanza.lines() {
-        if line.trim().is_empty() {
+/// The line and column of a byte offset in the folded value of a field, assuming the
+/// field is written as `Name: value`.
+fn position(field: &FieldRef, mut offset: usize) -> (usize, usize) {
+    for (i, line) in field.value.split('\n').enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
             continue;
         }
-        if line.starts_with(' ') || line.starts_with('\t') {
-            // Continuation line.
-            current_value.push(' ');
-            current_value.push_str(line.trim());
-        } else {
-            if let Some(key) = current_key.take() {
-                fields.insert(key, current_value.trim().to_string());
-                current_value.clear();
-            }
-            if let Some(pos) = line.find(':') {
-                let key = line[..pos].trim().to_lowercase();
-                let value = line[pos + 1..].trim().to_string();
-                current_key = Some(key);
-                current_value = value;
+        if offset <= trimmed.len() {
+            let indent = if i == 0 {
+                field.name.len() + 2
             } else {
-                return Err(format!("Line without colon: {}", line).into());
-            }
+                line.len() - line.trim_start().len()
+            };
+            return (field.line + i, indent + offset + 1);
         }
+        offset -= trimmed.len() + 1;
     }
-    if let Some(key) = current_key {
-        fields.insert(key, current_value.trim().to_string());
+    (field.line, 1)
+}
+
+/// Parse the fields of a control file stanza into a map keyed by lowercase field name,
+/// with continuation lines folded into a single line.
+pub(crate) fn parse_fields(stanza: &str) -> Result<HashMap<String, String>, Box<dyn Error>> {
+    let paragraphs = parse_paragraphs(stanza)?;
+    match &paragraphs[..] {
+        [paragraph] => Ok(paragraph_fields(paragraph)),
+        [] => Err("Empty stanza".into()),
+        _ => Err("Expected a single stanza".into()),
     }
-    let package = fields.remove("package").ok_or("Missing Package field")?;
-    let version = fields.remove("version").ok_or("Missing Version field")?;
-    let depends = match fields.remove("depends") {
-        Some(s) => parse_dependency_field(&s),
-        None => parse_dependency_field(""),
+}
+
+/// The fields of a deb822 paragraph keyed by lowercase field name, with continuation
+/// lines folded into a single line.
+pub(crate) fn paragraph_fields(paragraph: &Paragraph) -> HashMap<String, String> {
+    paragraph
+        .fields()
+        .iter()
+        .map(|field| {
+            let name = field.name.to_lowercase();
+            let value = paragraph.get_folded(&field.name).unwrap_or_default();
+            (name, value)
+        })
+        .collect()
+}
+
+/// Parse a single control file stanza into a DebianPackage.
+pub fn parse_debian_package(stanza: &str) -> Result<DebianPackage, ParseError> {
+    let mut paragraphs = paragraphs(stanza);
+    let paragraph = paragraphs
+        .next()
+        .ok_or(ParseError::new(ParseErrorKind::MissingField("Package")))??;
+    if let Some(next) = paragraphs.next() {
+        let line = next.map_or(0, |next| next.fields()[0].line);
+        let kind = ParseErrorKind::InvalidValue("Expected a single stanza".to_string());
+        return Err(ParseError::new(kind).in_stanza(1).at(line, 1));
+    }
+    debian_package_from_paragraph(&paragraph, 0, &mut Diagnostics::default())
+}
+
+/// Build a DebianPackage from the paragraph of a stanza, reporting malformed fields to the
+/// diagnostics.
+pub(crate) fn debian_package_from_paragraph(
+    paragraph: &ParagraphRef,
+    stanza: usize,
+    diagnostics: &mut Diagnostics,
+) -> Result<DebianPackage, ParseError> {
+    let field = |name: &str| paragraph.get_folded(name).map(|value| value.into_owned());
+    // An empty value is taken as missing: an empty Version would be taken for the
+    // not installed sentinel, see [`not_installed`](crate::debian_deps::not_installed).
+    let required = |name: &'static str| {
+        let line = paragraph.fields().first().map_or(0, |field| field.line);
+        field(name)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| {
+                ParseError::new(ParseErrorKind::MissingField(name))
+                    .in_stanza(stanza)
+                    .at(line, 1)
+            })
     };
-    let provides = match fields.remove("provides") {
-        Some(s) => parse_dependency_field(&s),
-        None => parse_dependency_field(""),
+    let mut relations = |name: &str| paragraph_relations(paragraph, stanza, name, diagnostics);
+    let mut dp = DebianPackage {
+        package: required("Package")?,
+        version: required("Version")?,
+        depends: relations("Depends")?,
+        pre_depends: relations("Pre-Depends")?,
+        recommends: relations("Recommends")?,
+        suggests: relations("Suggests")?,
+        enhances: relations("Enhances")?,
+        provides: relations("Provides")?,
+        conflicts: relations("Conflicts")?,
+        breaks: relations("Breaks")?,
+        architecture: field("architecture"),
+        multi_arch: field("multi-arch"),
+        essential: field("essential").is_some_and(|s| s == "yes"),
+        priority: field("priority"),
     };
 
-    Ok(DebianPackage {
-        package,
-        version,
-        depends,
-        provides,
-    })
+    let located = |kind: ParseErrorKind, name: &str| {
+        let line = paragraph
+            .fields()
+            .iter()
+            .find(|field| field.name.eq_ignore_ascii_case(name))
+            .map_or(0, |field| field.line);
+        ParseError::new(kind)
+            .in_stanza(stanza)
+            .in_field(name)
+            .at(line, name.len() + 3)
+    };
+    if !DebianVersion(dp.version.clone()).is_valid() {
+        let kind = ParseErrorKind::InvalidValue(dp.version);
+        return Err(located(kind, "Version"));
+    }
+    if let Some(multi_arch) = &dp.multi_arch {
+        if multi_arch.parse::<MultiArch>().is_err() {
+            let kind = ParseErrorKind::InvalidValue(multi_arch.clone());
+            diagnostics.report(located(kind, "Multi-Arch"))?;
+            dp.multi_arch = None;
+        }
+    }
+    let mut provides = Vec::new();
+    for provided in std::mem::take(&mut dp.provides) {
+        if provided.alternatives.len() == 1 {
+            provides.push(provided);
+        } else {
+            let item = provided
+                .alternatives
+                .iter()
+                .map(|alt| alt.package.as_str())
+                .collect::<Vec<_>>()
+                .join(" | ");
+            diagnostics.report(located(
+                ParseErrorKind::ProvidesAlternatives(item),
+                "Provides",
+            ))?;
+        }
+    }
+    dp.provides = provides;
+    Ok(dp)
+}
+
+/// Iterate over the packages of control file text, parsing one stanza at a time.
+///
+/// In lenient mode, stanzas that cannot be parsed are skipped and reported as warnings.
+pub fn debian_packages<'a>(
+    content: &'a str,
+    diagnostics: &'a mut Diagnostics,
+) -> impl Iterator<Item = Result<DebianPackage, ParseError>> + 'a {
+    paragraphs(content)
+        .enumerate()
+        .filter_map(move |(stanza, paragraph)| {
+            let dp = paragraph.and_then(|paragraph| {
+                debian_package_from_paragraph(&paragraph, stanza, diagnostics)
+            });
+            match dp {
+                Ok(dp) => Some(Ok(dp)),
+                Err(e) => diagnostics.report(e).err().map(Err),
+            }
+        })
 }
 
 /// Parse an entire control file (which may contain multiple stanzas)
-/// into a vector of DebianPackage entries.
-pub fn parse_debian_control<P: AsRef<Path>>(path: P) -> Result<Vec<DebianPackage>, Box<dyn Error>> {
-    let content = fs::read_to_string(path)?;
-    let stanzas: Vec<&str> = content
-        .split("\n\n")
-        .filter(|s| !s.trim().is_empty())
-        .collect();
-    let mut packages = Vec::new();
-    for stanza in stanzas {
-        packages.push(parse_debian_package(stanza)?);
-    }
-    Ok(packages)
+/// into a vector of DebianPackage entries. Compressed files are decompressed.
+pub fn parse_debian_control<P: AsRef<Path>>(path: P) -> Result<Vec<DebianPackage>, ParseError> {
+    let path = path.as_ref();
+    let content =
+        compression::read_to_string(path).map_err(|e| ParseError::from(e).in_file(path))?;
+    debian_packages(&content, &mut Diagnostics::default())
+        .collect::<Result<_, _>>()
+        .map_err(|e| e.in_file(path))
 }
 
 pub fn version_constraint_to_range(
@@ -269,54 +478,249 @@ fn convert_alternative(alt: &Alternative) -> index::Alternative {
     index::Alternative {
         name: alt.package.clone(),
         range: HashedRange(range),
+        arch: alt.arch_qualifier.clone(),
     }
 }
 
-fn convert_dependency(dep: &Dependency) -> index::Dependency {
-    let alternatives = dep
+/// Convert a dependency for a given architecture and set of active build profiles,
+/// dropping the alternatives whose restrictions exclude them.
+///
+/// Returns `None` if no alternative applies, in which case the dependency is void.
+fn convert_dependency(
+    dep: &Dependency,
+    arch: &str,
+    profiles: &HashSet<String>,
+) -> Option<index::Dependency> {
+    let alternatives: Vec<index::Alternative> = dep
         .alternatives
         .iter()
-        .map(|alt| convert_alternative(alt))
+        .filter(|alt| match &alt.arch {
+            Some(restrictions) => arch::matches_restriction(restrictions, arch),
+            None => true,
+        })
+        .filter(|alt| profile::matches_profiles(&alt.profiles, profiles))
+        .map(convert_alternative)
         .collect();
-    index::Dependency { alternatives }
+    if alternatives.is_empty() {
+        None
+    } else {
+        Some(index::Dependency { alternatives })
+    }
+}
+
+/// Convert a parsed relationship field into index dependencies for a given architecture
+/// and set of active build profiles, e.g. `nocheck` or `stage1`.
+pub fn convert_dependency_field(
+    parsed: &[crate::parse::Dependency],
+    arch: &str,
+    profiles: &HashSet<String>,
+) -> Vec<index::Dependency> {
+    parsed
+        .iter()
+        .filter_map(|dep| convert_dependency(dep, arch, profiles))
+        .collect()
 }
 
-fn convert_dependency_field(parsed: &Vec<crate::parse::Dependency>) -> Vec<index::Dependency> {
-    parsed.iter().map(|dep| convert_dependency(dep)).collect()
+/// Conflicts and Breaks do not allow alternatives, so flatten them into a plain list.
+fn convert_conflict_field(parsed: &[crate::parse::Dependency]) -> Vec<index::Alternative> {
+    parsed
+        .iter()
+        .flat_map(|dep| dep.alternatives.iter().map(convert_alternative))
+        .collect()
 }
 
-pub fn create_index<P: AsRef<Path>>(path: P) -> Result<Index, Box<dyn Error>> {
-    let debian_packages = parse_debian_control(path)?;
+pub fn create_index<P: AsRef<Path>>(path: P) -> Result<Index, ParseError> {
     let mut index = Index::new();
-    for dp in debian_packages {
-        let ver = DebianVersion::from_str(&dp.version)
-            .map_err(|e| format!("Error parsing version {}: {}", dp.version, e))?;
-        let dependencies = convert_dependency_field(&dp.depends);
-        index.add_deps(&dp.package, ver, dependencies);
-        let provides = convert_dependency_field(&dp.provides);
-        for provided in provides {
-            match &provided.alternatives[..] {
-                [dep] => index.add_deps(
-                    dep.name.as_str(),
-                    DebianVersion(dp.package.clone()),
-                    // TODO versioned provides, Range::as_singleton(dep.range.0)?,
-                    vec![index::Dependency {
-                        alternatives: vec![index::Alternative {
-                            name: dp.package.clone(),
-                            range: HashedRange(Range::singleton(DebianVersion(dp.version.clone()))),
-                        }],
-                    }],
-                ),
-                _ => panic!(""),
-            };
-        }
+    load_packages(&mut index, path)?;
+    Ok(index)
+}
+
+/// Add the packages of a control file to an existing index, failing on the first
+/// malformed stanza.
+///
+/// Packages of architectures the index is not configured for are skipped, so that e.g.
+/// the `binary-amd64` and `binary-i386` Packages files can be loaded into one index.
+pub fn load_packages<P: AsRef<Path>>(index: &mut Index, path: P) -> Result<(), ParseError> {
+    load_packages_with(index, path, &mut Diagnostics::default())
+}
+
+/// Add the packages of a control file to an existing index, reporting malformed stanzas
+/// to the diagnostics.
+///
+/// Compressed files are decompressed as they are read. Uncompressed files are
+/// memory-mapped and parsed one stanza at a time, without copying them.
+pub fn load_packages_with<P: AsRef<Path>>(
+    index: &mut Index,
+    path: P,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ParseError> {
+    load_file(index, path.as_ref(), None, diagnostics)
+}
+
+/// Add the packages of a repository's Packages file to an existing index, recording the
+/// repository as their origin.
+///
+/// A version already known from another repository is taken to be the same package: it
+/// is kept as loaded first and only gains the origin.
+pub fn load_repository<P: AsRef<Path>>(
+    index: &mut Index,
+    path: P,
+    origin: &Origin,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ParseError> {
+    load_file(index, path.as_ref(), Some(origin), diagnostics)
+}
+
+/// Create an index from the Packages files of several repositories, such as a suite
+/// along with its updates and security suites, see [`load_repository`].
+pub fn create_merged_index<P: AsRef<Path>>(
+    repositories: &[(P, Origin)],
+    diagnostics: &mut Diagnostics,
+) -> Result<Index, ParseError> {
+    let mut index = Index::new();
+    for (path, origin) in repositories {
+        load_repository(&mut index, path, origin, diagnostics)?;
     }
     Ok(index)
 }
 
+fn load_file(
+    index: &mut Index,
+    path: &Path,
+    origin: Option<&Origin>,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ParseError> {
+    let mut load = |diagnostics: &mut Diagnostics| {
+        let file = fs::File::open(path)?;
+        let mut reader = BufReader::new(&file);
+        if Compression::detect(reader.fill_buf()?) != Compression::None {
+            return load_reader(index, reader, origin, diagnostics);
+        }
+        // SAFETY: the map is only read while the file is open, and the index files are not
+        // expected to be modified while they are loaded.
+        let map = unsafe { Mmap::map(&file)? };
+        let content = std::str::from_utf8(&map).map_err(|e| {
+            let line = map[..e.valid_up_to()]
+                .iter()
+                .filter(|&&b| b == b'\n')
+                .count()
+                + 1;
+            ParseError::new(ParseErrorKind::InvalidUtf8).at(line, 1)
+        })?;
+        for dp in debian_packages(content, diagnostics) {
+            add_debian_package(index, dp?, origin);
+        }
+        Ok(())
+    };
+    let reported = diagnostics.warnings.len();
+    let loaded = load(diagnostics).map_err(|e| e.in_file(path));
+    for warning in &mut diagnostics.warnings[reported..] {
+        warning.path.get_or_insert(path.to_path_buf());
+    }
+    loaded
+}
+
+/// Add the packages of a control file read from a stream to an existing index,
+/// holding only one stanza in memory at a time. Compressed input is decompressed.
+pub fn load_packages_from_reader<R: Read>(
+    index: &mut Index,
+    reader: R,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ParseError> {
+    load_reader(index, reader, None, diagnostics)
+}
+
+pub(crate) fn load_reader<R: Read>(
+    index: &mut Index,
+    reader: R,
+    origin: Option<&Origin>,
+    diagnostics: &mut Diagnostics,
+) -> Result<(), ParseError> {
+    let (_, reader) = compression::decompress(BufReader::new(reader))?;
+    for (stanza, paragraph) in ParagraphReader::new(BufReader::new(reader)).enumerate() {
+        let dp = paragraph.and_then(|paragraph| {
+            debian_package_from_paragraph(&paragraph.borrow(), stanza, diagnostics)
+        });
+        match dp {
+            Ok(dp) => add_debian_package(index, dp, origin),
+            Err(e) => diagnostics.report(e)?,
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn add_debian_package(index: &mut Index, dp: DebianPackage, origin: Option<&Origin>) {
+    let arch = dp.architecture.clone().unwrap_or_default();
+    if !index.accepts_arch(&arch) {
+        return;
+    }
+    let name = index.qualify(&dp.package, &arch);
+    let ver = DebianVersion(dp.version.clone());
+    // The same version from several repositories is the same package, only registered once.
+    let known = index
+        .packages
+        .get(&name)
+        .is_some_and(|v| v.contains_key(&ver));
+    if let Some(origin) = origin {
+        index.add_origin(&name, ver.clone(), origin.clone());
+    }
+    if known {
+        return;
+    }
+    let host = index.effective_arch(&arch).to_string();
+    let no_profiles = HashSet::new();
+    let multi_arch = dp
+        .multi_arch
+        .as_ref()
+        .and_then(|s| s.parse::<MultiArch>().ok())
+        .unwrap_or(MultiArch::No);
+    let dependencies = convert_dependency_field(&dp.depends, &host, &no_profiles);
+    index.add_deps(&name, ver.clone(), dependencies);
+    index.add_pre_deps(
+        &name,
+        ver.clone(),
+        convert_dependency_field(&dp.pre_depends, &host, &no_profiles),
+    );
+    index.add_soft_deps(
+        &name,
+        ver.clone(),
+        convert_dependency_field(&dp.recommends, &host, &no_profiles),
+        convert_dependency_field(&dp.suggests, &host, &no_profiles),
+        convert_dependency_field(&dp.enhances, &host, &no_profiles),
+    );
+    index.add_conflicts(
+        &name,
+        ver.clone(),
+        convert_conflict_field(&dp.conflicts),
+        convert_conflict_field(&dp.breaks),
+    );
+    index.add_arch(&name, ver.clone(), &arch, multi_arch);
+    // Unknown priorities such as "source" are not an error.
+    let priority = dp
+        .priority
+        .as_ref()
+        .and_then(|p| p.parse::<Priority>().ok());
+    index.add_essential(&name, ver.clone(), dp.essential, priority);
+    // Provides with alternatives were reported and dropped when parsing.
+    let provides = convert_dependency_field(&dp.provides, &host, &no_profiles);
+    for provided in provides {
+        if let [dep] = &provided.alternatives[..] {
+            index.add_provider(
+                dep.name.as_str(),
+                &name,
+                ver.clone(),
+                dep.range.0.as_singleton().cloned(),
+            );
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::debian_deps::{explain, Package, Solver};
+    use crate::error::Mode;
+    use pubgrub::PubGrubError;
 
     #[test]
     fn test_parse_dependency_alternative() {
@@ -333,10 +737,56 @@ mod tests {
         assert_eq!(archs, vec!["amd64".to_string(), "i386".to_string()]);
     }
 
+    #[test]
+    fn test_parse_arch_qualifier() {
+        let alt = parse_alternative("python3:any (>= 3.7)").unwrap();
+        assert_eq!(alt.package, "python3");
+        assert_eq!(alt.arch_qualifier, Some("any".to_string()));
+        assert!(alt.version_constraint.is_some());
+    }
+
+    #[test]
+    fn test_arch_restrictions() {
+        let s = "libseccomp-dev [!hurd-any], libc6-dev [linux-any] | libc-dev [!linux-any]";
+        let dependencies = parse_dependency_field(s).unwrap();
+        assert_eq!(dependencies[0].alternatives[0].package, "libseccomp-dev");
+
+        let amd64 = convert_dependency_field(&dependencies, "amd64", &HashSet::new());
+        assert_eq!(amd64.len(), 2);
+        assert_eq!(amd64[1].alternatives.len(), 1);
+        assert_eq!(amd64[1].alternatives[0].name, "libc6-dev");
+
+        let hurd = convert_dependency_field(&dependencies, "hurd-i386", &HashSet::new());
+        assert_eq!(hurd.len(), 1);
+        assert_eq!(hurd[0].alternatives[0].name, "libc-dev");
+    }
+
+    #[test]
+    fn test_build_profiles() {
+        let alt =
+            parse_alternative("dh-sequence-python3 (>= 5) [linux-any] <!nocheck> <cross>").unwrap();
+        assert_eq!(alt.package, "dh-sequence-python3");
+        assert_eq!(alt.arch, Some(vec!["linux-any".to_string()]));
+        assert_eq!(alt.profiles.len(), 2);
+        assert_eq!(alt.profiles[0], vec![ProfileTerm::from("!nocheck")]);
+
+        let s = "debhelper-compat (= 13), python3-pytest <!nocheck>, gcc <stage1 !cross>";
+        let dependencies = parse_dependency_field(s).unwrap();
+        assert_eq!(dependencies[1].alternatives[0].package, "python3-pytest");
+        let default = convert_dependency_field(&dependencies, "amd64", &HashSet::new());
+        assert_eq!(default.len(), 2);
+        let nocheck = ["nocheck".to_string()].into();
+        let nocheck = convert_dependency_field(&dependencies, "amd64", &nocheck);
+        assert_eq!(nocheck.len(), 1);
+        let stage1 = ["stage1".to_string()].into();
+        let stage1 = convert_dependency_field(&dependencies, "amd64", &stage1);
+        assert_eq!(stage1.len(), 3);
+    }
+
     #[test]
     fn test_parse_dependency_field() {
         let s = "libc6 (>= 2.2.1), default-mta | mail-transport-agent";
-        let dependencies = parse_dependency_field(s);
+        let dependencies = parse_dependency_field(s).unwrap();
         assert_eq!(dependencies.len(), 2);
 
         let dep1 = &dependencies[0];
@@ -365,6 +815,179 @@ Description: Email client
         assert_eq!(pkg.package, "mutt");
         assert_eq!(pkg.version, "1.3.17-1");
         assert_eq!(pkg.depends.len(), 2);
+
+        let error = parse_debian_package("Package: mutt\nVersion:\n").unwrap_err();
+        assert!(matches!(
+            error.kind,
+            ParseErrorKind::MissingField("Version")
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_pre_depends() -> Result<(), Box<dyn Error>> {
+        let sample = r#"Package: libgcc1
+Version: 1:8.3.0-6
+Multi-Arch: same
+Pre-Depends: multiarch-support
+Depends: gcc-8-base (= 8.3.0-6), libc6 (>= 2.14)
+"#;
+        let pkg = parse_debian_package(sample)?;
+        assert_eq!(pkg.depends.len(), 2);
+        assert_eq!(pkg.pre_depends.len(), 1);
+        assert_eq!(
+            pkg.pre_depends[0].alternatives[0].package,
+            "multiarch-support"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_packages() -> Result<(), Box<dyn Error>> {
+        let sample = "Package: a\r\nVersion: 1\r\nDepends: b,\r\n c\r\n \r\nPackage: b\nVersion: 2\n\nPackage: c\nVersion: 3\n";
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("Packages");
+        fs::write(&path, sample)?;
+        let mut mapped = Index::new();
+        load_packages(&mut mapped, &path)?;
+        let mut streamed = Index::new();
+        load_packages_from_reader(
+            &mut streamed,
+            sample.as_bytes(),
+            &mut Diagnostics::default(),
+        )?;
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut gz, sample.as_bytes())?;
+        let mut compressed = Index::new();
+        load_packages_from_reader(
+            &mut compressed,
+            &gz.finish()?[..],
+            &mut Diagnostics::default(),
+        )?;
+        for index in [&mapped, &streamed, &compressed] {
+            assert_eq!(index.packages.len(), 3);
+            let a = &index.packages["a"][&DebianVersion("1".to_string())];
+            assert_eq!(a.depends.len(), 2);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_create_merged_index() -> Result<(), Box<dyn Error>> {
+        let root = tempfile::tempdir()?;
+        let dists = root.path().join("dists");
+        let main = dists.join("bookworm/main/binary-amd64/Packages");
+        let security = dists.join("bookworm-security/main/binary-amd64/Packages");
+        for path in [&main, &security] {
+            fs::create_dir_all(path.parent().unwrap())?;
+        }
+        fs::write(
+            &main,
+            "Package: lib\nVersion: 1\nProvides: libapi\n\nPackage: app\nVersion: 1\nDepends: lib (>> 1)\n",
+        )?;
+        fs::write(
+            &security,
+            "Package: lib\nVersion: 1\nProvides: libapi\n\nPackage: lib\nVersion: 1+deb12u1\nDepends: libnew\n",
+        )?;
+        fs::write(
+            dists.join("bookworm-security/Release"),
+            "Origin: Debian\nLabel: Debian-Security\nSuite: stable-security\n",
+        )?;
+        let repositories: Vec<_> = [&main, &security]
+            .into_iter()
+            .map(|path| (path, Origin::from_path(path)))
+            .collect();
+        let index = create_merged_index(&repositories, &mut Diagnostics::default())?;
+
+        let v = |version: &str| DebianVersion(version.to_string());
+        let origins: Vec<String> = index
+            .origins("lib", &v("1"))
+            .iter()
+            .map(|origin| origin.to_string())
+            .collect();
+        assert_eq!(
+            origins,
+            vec!["bookworm/main", "Debian-Security stable-security/main"]
+        );
+        assert_eq!(index.origins("lib", &v("1+deb12u1")).len(), 1);
+        assert_eq!(index.providers["libapi"].len(), 1);
+
+        let root = Package::Root(vec![(Package::Base("app".to_string()), Range::full())]);
+        match pubgrub::resolve(&Solver::from(&index), root, v("")) {
+            Err(PubGrubError::NoSolution(mut derivation_tree)) => {
+                let report = explain(&index, &mut derivation_tree);
+                assert!(
+                    report.contains("lib 1+deb12u1 is from Debian-Security stable-security/main")
+                );
+                assert!(!report.contains("lib 1 is from"));
+            }
+            _ => panic!("app should not be installable"),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_diagnostics() -> Result<(), Box<dyn Error>> {
+        let sample = "Package: a\nVersion: 1\nDepends: b, c (>= 1\nProvides: x | y\n\nPackage: b\n\nPackage: c\nVersion: 1\nBroken line\n\nPackage: d\nVersion: 2\nDepends: a,\n e (>> 1\n";
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("Packages");
+        fs::write(&path, sample)?;
+        let mut index = Index::new();
+        let strict = load_packages(&mut index, &path);
+        let mut mapped = Index::new();
+        let mut lenient = Diagnostics::new(Mode::Lenient);
+        load_packages_with(&mut mapped, &path, &mut lenient)?;
+
+        let error = strict.unwrap_err();
+        assert!(matches!(error.kind, ParseErrorKind::InvalidRelation { .. }));
+        assert_eq!(error.path.as_ref(), Some(&path));
+        assert_eq!((error.stanza, error.line, error.column), (Some(0), 3, 13));
+
+        let mut streamed = Index::new();
+        let mut stream_lenient = Diagnostics::new(Mode::Lenient);
+        load_packages_from_reader(&mut streamed, sample.as_bytes(), &mut stream_lenient)?;
+        for (index, diagnostics) in [(&mapped, &lenient), (&streamed, &stream_lenient)] {
+            let mut names: Vec<_> = index.packages.keys().collect();
+            names.sort();
+            assert_eq!(names, vec!["a", "d"]);
+            assert!(index.providers.is_empty());
+            let locations: Vec<_> = diagnostics
+                .warnings
+                .iter()
+                .map(|w| (w.stanza, w.field.as_deref(), w.line, w.column))
+                .collect();
+            assert_eq!(
+                locations,
+                vec![
+                    (Some(0), Some("Depends"), 3, 13),
+                    (Some(0), Some("Provides"), 4, 11),
+                    (Some(1), None, 6, 1),
+                    (Some(2), None, 10, 1),
+                    (Some(3), Some("Depends"), 15, 2),
+                ]
+            );
+        }
+        assert!(lenient.warnings[1].to_string().ends_with(
+            ":4:11: stanza 0: Provides: Alternatives are not allowed in Provides: x | y"
+        ));
+
+        let versions = "Package: e\nVersion: 1:\n\nPackage: f\nVersion: 2.0-1\n";
+        let mut index = Index::new();
+        let mut diagnostics = Diagnostics::new(Mode::Lenient);
+        load_packages_from_reader(&mut index, versions.as_bytes(), &mut diagnostics)?;
+        assert_eq!(index.packages.keys().collect::<Vec<_>>(), vec!["f"]);
+        let warning = &diagnostics.warnings[0];
+        assert!(matches!(&warning.kind, ParseErrorKind::InvalidValue(v) if v == "1:"));
+        assert_eq!(
+            (warning.field.as_deref(), warning.line),
+            (Some("Version"), 2)
+        );
+        let strict = load_packages_from_reader(
+            &mut Index::new(),
+            versions.as_bytes(),
+            &mut Diagnostics::default(),
+        );
+        assert!(strict.is_err());
         Ok(())
     }
 
@@ -406,93 +1029,111 @@ SHA256: 65bb2ee2cfce60b83523754c3768578417bbb23af760ddd26d53999f4da0f4e6
                     Dependency {
                         alternatives: [Alternative {
                             package: "adduser".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "3.9".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "dpkg".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "1.9.0".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libpam-modules".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "0.72-9".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libpam-runtime".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "0.76-14".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "lsb-base".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "4.1+Debian3".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "openssh-client".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::ExactlyEqual,
                                 version: "1:7.9p1-10+deb10u2".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "openssh-sftp-server".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: None,
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "procps".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: None,
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "ucf".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "0.28".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
@@ -500,16 +1141,20 @@ SHA256: 65bb2ee2cfce60b83523754c3768578417bbb23af760ddd26d53999f4da0f4e6
                         alternatives: [
                             Alternative {
                                 package: "debconf".to_owned(),
+                                arch_qualifier: None,
                                 version_constraint: Some(VersionConstraint {
                                     relation: VersionRelation::LaterOrEqual,
                                     version: "0.5".to_owned()
                                 }),
-                                arch: None
+                                arch: None,
+                                profiles: Vec::new()
                             },
                             Alternative {
                                 package: "debconf-2.0".to_owned(),
+                                arch_qualifier: None,
                                 version_constraint: None,
-                                arch: None
+                                arch: None,
+                                profiles: Vec::new()
                             }
                         ]
                         .to_vec()
@@ -517,132 +1162,208 @@ SHA256: 65bb2ee2cfce60b83523754c3768578417bbb23af760ddd26d53999f4da0f4e6
                     Dependency {
                         alternatives: [Alternative {
                             package: "libaudit1".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "1:2.2.1".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libc6".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "2.26".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libcom-err2".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "1.43.9".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libgssapi-krb5-2".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "1.17".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libkrb5-3".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "1.13~alpha1+dfsg".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libpam0g".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "0.99.7.1".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libselinux1".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "1.32".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libssl1.1".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "1.1.1".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libsystemd0".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: None,
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "libwrap0".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "7.6-4~".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     },
                     Dependency {
                         alternatives: [Alternative {
                             package: "zlib1g".to_owned(),
+                            arch_qualifier: None,
                             version_constraint: Some(VersionConstraint {
                                 relation: VersionRelation::LaterOrEqual,
                                 version: "1:1.1.4".to_owned()
                             }),
-                            arch: None
+                            arch: None,
+                            profiles: Vec::new()
                         }]
                         .to_vec()
                     }
                 ]
                 .to_vec(),
+                pre_depends: Vec::new(),
+                recommends: [
+                    vec!["default-logind", "logind", "libpam-systemd"],
+                    vec!["ncurses-term"],
+                    vec!["xauth"]
+                ]
+                .iter()
+                .map(|names| Dependency {
+                    alternatives: names
+                        .iter()
+                        .map(|name| Alternative {
+                            package: name.to_string(),
+                            arch_qualifier: None,
+                            version_constraint: None,
+                            arch: None,
+                            profiles: Vec::new()
+                        })
+                        .collect()
+                })
+                .collect(),
+                suggests: ["molly-guard", "monkeysphere", "rssh", "ssh-askpass", "ufw"]
+                    .iter()
+                    .map(|name| Dependency {
+                        alternatives: [Alternative {
+                            package: name.to_string(),
+                            arch_qualifier: None,
+                            version_constraint: None,
+                            arch: None,
+                            profiles: Vec::new()
+                        }]
+                        .to_vec()
+                    })
+                    .collect(),
+                enhances: Vec::new(),
                 provides: [Dependency {
                     alternatives: [Alternative {
                         package: "ssh-server".to_owned(),
+                        arch_qualifier: None,
                         version_constraint: None,
-                        arch: None
+                        arch: None,
+                        profiles: Vec::new()
                     }]
                     .to_vec()
                 }]
-                .to_vec()
+                .to_vec(),
+                conflicts: ["sftp", "ssh-socks", "ssh2"]
+                    .iter()
+                    .map(|name| Dependency {
+                        alternatives: [Alternative {
+                            package: name.to_string(),
+                            arch_qualifier: None,
+                            version_constraint: None,
+                            arch: None,
+                            profiles: Vec::new()
+                        }]
+                        .to_vec()
+                    })
+                    .collect(),
+                breaks: Vec::new(),
+                architecture: Some("amd64".to_owned()),
+                multi_arch: Some("foreign".to_owned()),
+                essential: false,
+                priority: Some("optional".to_owned())
             }
         );
         Ok(())
@@ -0,0 +1,455 @@
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::deb822::{paragraphs, FieldRef, ParagraphRef};
+use crate::debian_version::DebianVersion;
+use crate::error::{Diagnostics, ParseError, ParseErrorKind};
+use crate::index::{unqualified, Index, Origin};
+
+/// What a pin matches, the value of the `Pin` field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pin {
+    /// `Pin: release a=bookworm-backports, c=main`: conditions on the Release file of the
+    /// repository, as pairs of a key (`a`, `n`, `o`, `l`, `c` or `v`) and a pattern.
+    /// A plain value, as in `Pin: release 12`, is a `v=` condition.
+    Release(Vec<(char, String)>),
+    /// `Pin: origin deb.debian.org`: the host the repository is downloaded from.
+    Origin(String),
+    /// `Pin: version 5.10.*`.
+    Version(String),
+}
+
+impl FromStr for Pin {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, value) = s.split_once(char::is_whitespace).unwrap_or((s, ""));
+        let value = value.trim();
+        match kind {
+            "release" => {
+                let mut conditions = Vec::new();
+                for condition in value.split(',').map(str::trim).filter(|c| !c.is_empty()) {
+                    let (key, pattern) = match condition.split_once('=') {
+                        Some((key, pattern)) => (key.trim(), pattern.trim()),
+                        None => ("v", condition),
+                    };
+                    match key {
+                        "a" | "n" | "o" | "l" | "c" | "v" => {
+                            conditions.push((key.chars().next().unwrap(), pattern.to_string()))
+                        }
+                        _ => return Err(format!("Unknown release condition: {}", condition)),
+                    }
+                }
+                Ok(Pin::Release(conditions))
+            }
+            "origin" => Ok(Pin::Origin(value.trim_matches('"').to_string())),
+            "version" => Ok(Pin::Version(value.to_string())),
+            _ => Err(format!("Invalid pin: {}", s)),
+        }
+    }
+}
+
+impl Pin {
+    /// Whether a package version from the given origin, if known, matches the pin.
+    pub fn matches(&self, version: &DebianVersion, origin: Option<&Origin>) -> bool {
+        match (self, origin) {
+            (Pin::Version(pattern), _) => glob_match(pattern, &version.0),
+            (Pin::Release(conditions), Some(origin)) => conditions.iter().all(|(key, pattern)| {
+                let value = match key {
+                    'a' => &origin.suite,
+                    'n' => &origin.codename,
+                    'o' => &origin.origin,
+                    'l' => &origin.label,
+                    'c' => &origin.component,
+                    _ => &origin.version,
+                };
+                glob_match(pattern, value)
+            }),
+            (Pin::Origin(site), Some(origin)) => origin.site == *site,
+            (_, None) => false,
+        }
+    }
+}
+
+/// A record of an apt preferences file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinRecord {
+    /// Package name patterns, `*` for a general record applying to every package.
+    pub packages: Vec<String>,
+    pub pin: Pin,
+    pub priority: i32,
+}
+
+impl PinRecord {
+    pub fn is_general(&self) -> bool {
+        self.packages.iter().any(|pattern| pattern == "*")
+    }
+
+    /// Whether the record names the package, given by its architecture-qualified name.
+    pub fn applies_to(&self, name: &str) -> bool {
+        self.packages
+            .iter()
+            .any(|pattern| glob_match(pattern, name) || glob_match(pattern, unqualified(name)))
+    }
+}
+
+/// The pins of `/etc/apt/preferences` and `/etc/apt/preferences.d`, see apt_preferences(5).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Preferences {
+    pub records: Vec<PinRecord>,
+}
+
+/// Whether a value matches a pattern where `*` stands for any string and `?` for any
+/// character, as in fnmatch(3).
+fn glob_match(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    // Position of the last `*` and of the value it was tried at.
+    let mut star = None;
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, v));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match star {
+                Some((star_p, star_v)) => {
+                    p = star_p + 1;
+                    v = star_v + 1;
+                    star = Some((star_p, star_v + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn pin_record(paragraph: &ParagraphRef) -> Result<PinRecord, ParseError> {
+    let line = paragraph.fields()[0].line;
+    let field = |name: &'static str| {
+        paragraph
+            .fields()
+            .iter()
+            .find(|field| field.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| ParseError::new(ParseErrorKind::MissingField(name)).at(line, 1))
+    };
+    let invalid = |field: &FieldRef, message: String| {
+        ParseError::new(ParseErrorKind::InvalidValue(message))
+            .in_field(field.name)
+            .at(field.line, field.name.len() + 3)
+    };
+    let packages = field("Package")?;
+    if let Some(regex) = packages
+        .value
+        .split_whitespace()
+        .find(|p| p.starts_with('/'))
+    {
+        let message = format!("Regular expressions are not supported: {}", regex);
+        return Err(invalid(packages, message));
+    }
+    let pin = field("Pin")?;
+    let priority = field("Pin-Priority")?;
+    Ok(PinRecord {
+        packages: packages
+            .value
+            .split_whitespace()
+            .map(String::from)
+            .collect(),
+        pin: pin.value.parse().map_err(|e| invalid(pin, e))?,
+        priority: match priority.value.trim().parse() {
+            Ok(0) | Err(_) => {
+                let message = format!("Invalid priority: {}", priority.value);
+                return Err(invalid(priority, message));
+            }
+            Ok(priority) => priority,
+        },
+    })
+}
+
+/// Parse an apt preferences file.
+pub fn parse_preferences(
+    content: &str,
+    diagnostics: &mut Diagnostics,
+) -> Result<Preferences, ParseError> {
+    let mut preferences = Preferences::default();
+    for (stanza, paragraph) in paragraphs(content).enumerate() {
+        let record = paragraph.and_then(|paragraph| pin_record(&paragraph));
+        match record {
+            Ok(record) => preferences.records.push(record),
+            Err(e) => diagnostics.report(e.in_stanza(stanza))?,
+        }
+    }
+    Ok(preferences)
+}
+
+/// Load the preferences of an apt configuration directory such as `/etc/apt`.
+///
+/// As in apt, `preferences` is read first, then the files of `preferences.d` in
+/// alphabetical order, skipping those with an extension other than `.pref`.
+pub fn load_preferences<P: AsRef<Path>>(
+    etc: P,
+    diagnostics: &mut Diagnostics,
+) -> Result<Preferences, ParseError> {
+    let etc = etc.as_ref();
+    let mut paths = vec![etc.join("preferences")];
+    if let Ok(dir) = fs::read_dir(etc.join("preferences.d")) {
+        let mut parts = Vec::new();
+        for entry in dir {
+            let path = entry?.path();
+            if path.extension().is_none_or(|extension| extension == "pref") {
+                parts.push(path);
+            }
+        }
+        parts.sort();
+        paths.extend(parts);
+    }
+    let mut preferences = Preferences::default();
+    for path in paths.iter().filter(|path| path.is_file()) {
+        let content = fs::read_to_string(path).map_err(|e| ParseError::from(e).in_file(path))?;
+        let reported = diagnostics.warnings.len();
+        let parsed = parse_preferences(&content, diagnostics).map_err(|e| e.in_file(path))?;
+        for warning in &mut diagnostics.warnings[reported..] {
+            warning.path.get_or_insert(path.to_path_buf());
+        }
+        preferences.records.extend(parsed.records);
+    }
+    Ok(preferences)
+}
+
+impl Index {
+    /// Priority of a package version, as `apt-cache policy` shows it.
+    ///
+    /// The first record naming the package whose pin matches the version gives its
+    /// priority. Otherwise each origin of the version gets the priority of the first
+    /// general record matching it, or by default 1 for `NotAutomatic` archives, 100 if they
+    /// also have `ButAutomaticUpgrades`, and 500 for others, and the version gets the
+    /// highest of those. Candidates also give the installed version a priority of at least
+    /// 100, see [`Index::candidate_versions`].
+    pub fn pin_priority(&self, name: &str, version: &DebianVersion) -> i32 {
+        let origins = self.origins(name, version);
+        let matches = |record: &&PinRecord| match origins {
+            [] => record.pin.matches(version, None),
+            origins => origins
+                .iter()
+                .any(|origin| record.pin.matches(version, Some(origin))),
+        };
+        let records = &self.preferences.records;
+        let specific = records
+            .iter()
+            .filter(|record| !record.is_general() && record.applies_to(name));
+        if let Some(record) = specific.clone().find(matches) {
+            return record.priority;
+        }
+        let general = |origin: Option<&Origin>| {
+            records
+                .iter()
+                .filter(|record| record.is_general())
+                .find(|record| record.pin.matches(version, origin))
+                .map(|record| record.priority)
+        };
+        match origins {
+            [] => general(None).unwrap_or(500),
+            origins => origins
+                .iter()
+                .map(|origin| {
+                    general(Some(origin)).unwrap_or(
+                        match (origin.not_automatic, origin.but_automatic_upgrades) {
+                            (true, true) => 100,
+                            (true, false) => 1,
+                            (false, _) => 500,
+                        },
+                    )
+                })
+                .max()
+                .unwrap_or(500),
+        }
+    }
+
+    /// Versions of a package in the order apt's candidate policy prefers them, the
+    /// candidate first.
+    ///
+    /// Versions are ordered by priority, then newest first. The `installed` version has a
+    /// priority of at least 100. Versions with a negative priority are left out, unless
+    /// installed, and versions older than the installed one come last unless their priority
+    /// is at least 1000.
+    pub fn candidate_versions(
+        &self,
+        name: &str,
+        installed: Option<&DebianVersion>,
+    ) -> Vec<DebianVersion> {
+        let priority = |version: &DebianVersion| match self.pin_priority(name, version) {
+            priority if Some(version) == installed => priority.max(100),
+            priority => priority,
+        };
+        let mut versions: Vec<(i32, DebianVersion)> = self
+            .available_versions(&name.to_string())
+            .into_iter()
+            .map(|version| (priority(&version), version))
+            .filter(|(priority, version)| *priority >= 0 || Some(version) == installed)
+            .collect();
+        // The sort is stable, so versions of the same priority stay newest first.
+        versions.sort_by_key(|(priority, version)| {
+            let downgrade = installed.is_some_and(|installed| version < installed);
+            (downgrade && *priority < 1000, -priority)
+        });
+        versions.into_iter().map(|(_, version)| version).collect()
+    }
+
+    /// The version apt would install, see [`Index::candidate_versions`].
+    pub fn candidate(
+        &self,
+        name: &str,
+        installed: Option<&DebianVersion>,
+    ) -> Option<DebianVersion> {
+        self.candidate_versions(name, installed).into_iter().next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::debian_deps::{Package, Solver};
+    use crate::error::Mode;
+    use pubgrub::Range;
+
+    const PREFERENCES: &str = r#"# Kernel held at 6.1
+Package: linux-image-amd64
+Pin: version 6.1.*
+Pin-Priority: 1001
+
+Package: *
+Pin: release a=bookworm-backports
+Pin-Priority: 100
+
+Package: cockpit cockpit-*
+Pin: release n=bookworm-backports
+Pin-Priority: 990
+
+Package: *
+Pin: origin "ppa.example.org"
+Pin-Priority: -1
+"#;
+
+    fn v(version: &str) -> DebianVersion {
+        DebianVersion(version.to_string())
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("6.1.*", "6.1.76-1"));
+        assert!(glob_match("cockpit-*", "cockpit-ws"));
+        assert!(glob_match("a?c", "abc"));
+        assert!(glob_match("*a*b", "xaxab"));
+        assert!(!glob_match("6.1.*", "6.10.1"));
+        assert!(!glob_match("cockpit-*", "cockpit"));
+    }
+
+    #[test]
+    fn test_parse_preferences() -> Result<(), Box<dyn Error>> {
+        let preferences = parse_preferences(PREFERENCES, &mut Diagnostics::default())?;
+        assert_eq!(preferences.records.len(), 4);
+        assert_eq!(
+            preferences.records[0].pin,
+            Pin::Version("6.1.*".to_string())
+        );
+        assert_eq!(
+            preferences.records[1].pin,
+            Pin::Release(vec![('a', "bookworm-backports".to_string())])
+        );
+        assert!(preferences.records[1].is_general());
+        assert!(preferences.records[2].applies_to("cockpit-ws:i386"));
+        assert_eq!(
+            preferences.records[3].pin,
+            Pin::Origin("ppa.example.org".to_string())
+        );
+
+        let invalid =
+            "Package: /^foo/\nPin: version 1\nPin-Priority: 1\n\nPackage: bar\nPin: version 1\n";
+        let mut diagnostics = Diagnostics::new(Mode::Lenient);
+        assert!(parse_preferences(invalid, &mut diagnostics)?
+            .records
+            .is_empty());
+        assert_eq!(diagnostics.warnings.len(), 2);
+        assert_eq!(diagnostics.warnings[0].line, 1);
+        assert!(parse_preferences(invalid, &mut Diagnostics::default()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_candidate_policy() -> Result<(), Box<dyn Error>> {
+        let bookworm = Origin {
+            origin: "Debian".to_string(),
+            suite: "bookworm".to_string(),
+            codename: "bookworm".to_string(),
+            component: "main".to_string(),
+            site: "deb.debian.org".to_string(),
+            ..Origin::default()
+        };
+        let backports = Origin {
+            suite: "bookworm-backports".to_string(),
+            codename: "bookworm-backports".to_string(),
+            not_automatic: true,
+            but_automatic_upgrades: true,
+            ..bookworm.clone()
+        };
+        let ppa = Origin {
+            site: "ppa.example.org".to_string(),
+            ..Origin::default()
+        };
+        let mut index = Index::new();
+        for (name, version, origin) in [
+            ("linux-image-amd64", "6.1.76-1", &bookworm),
+            ("linux-image-amd64", "6.6.13-1~bpo12+1", &backports),
+            ("cockpit", "287-1", &bookworm),
+            ("cockpit", "310-1~bpo12+1", &backports),
+            ("hello", "2.10-3", &bookworm),
+            ("hello", "2.12-1~bpo12+1", &backports),
+            ("hello", "3.0-1", &ppa),
+        ] {
+            index.add_deps(name, v(version), vec![]);
+            index.add_origin(name, v(version), origin.clone());
+        }
+
+        // Without preferences, only the backports defaults apply.
+        assert_eq!(index.pin_priority("hello", &v("2.12-1~bpo12+1")), 100);
+        assert_eq!(index.candidate("hello", None), Some(v("3.0-1")));
+
+        index.preferences = parse_preferences(PREFERENCES, &mut Diagnostics::default())?;
+        assert_eq!(
+            index.pin_priority("linux-image-amd64", &v("6.1.76-1")),
+            1001
+        );
+        assert_eq!(
+            index.candidate("linux-image-amd64", None),
+            Some(v("6.1.76-1"))
+        );
+        assert_eq!(index.candidate("cockpit", None), Some(v("310-1~bpo12+1")));
+        assert_eq!(index.pin_priority("hello", &v("3.0-1")), -1);
+        assert_eq!(
+            index.candidate_versions("hello", None),
+            vec![v("2.10-3"), v("2.12-1~bpo12+1")]
+        );
+        let root = Package::Root(vec![(Package::Base("hello".to_string()), Range::full())]);
+        let sol = Solver::from(&index).resolve(root, v(""))?;
+        assert_eq!(
+            sol.get(&Package::Base("hello".to_string())),
+            Some(&v("2.10-3"))
+        );
+
+        // Installed versions are not downgraded, even from a higher priority.
+        assert_eq!(
+            index.candidate_versions("hello", Some(&v("3.0-1"))),
+            vec![v("3.0-1"), v("2.10-3"), v("2.12-1~bpo12+1")]
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+
+/// A term of a build profile restriction, e.g. `nocheck` or `!stage1`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ProfileTerm {
+    pub negated: bool,
+    pub profile: String,
+}
+
+impl From<&str> for ProfileTerm {
+    fn from(s: &str) -> Self {
+        match s.strip_prefix('!') {
+            Some(profile) => ProfileTerm {
+                negated: true,
+                profile: profile.to_string(),
+            },
+            None => ProfileTerm {
+                negated: false,
+                profile: s.to_string(),
+            },
+        }
+    }
+}
+
+/// A restriction formula such as `<!nocheck> <stage1 cross>`.
+///
+/// Each `<...>` group is the conjunction of its terms, and the formula is the disjunction
+/// of its groups.
+pub type RestrictionFormula = Vec<Vec<ProfileTerm>>;
+
+/// Evaluate a restriction formula against the set of active build profiles.
+///
+/// An empty formula always matches.
+pub fn matches_profiles(formula: &[Vec<ProfileTerm>], active: &HashSet<String>) -> bool {
+    formula.is_empty()
+        || formula.iter().any(|group| {
+            group
+                .iter()
+                .all(|term| active.contains(&term.profile) != term.negated)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formula(groups: &[&str]) -> RestrictionFormula {
+        groups
+            .iter()
+            .map(|group| group.split_whitespace().map(ProfileTerm::from).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_matches_profiles() {
+        let none = HashSet::new();
+        let nocheck: HashSet<String> = ["nocheck".to_string()].into();
+        let stage1_cross: HashSet<String> = ["stage1".to_string(), "cross".to_string()].into();
+
+        assert!(matches_profiles(&formula(&[]), &none));
+        assert!(matches_profiles(&formula(&["!nocheck"]), &none));
+        assert!(!matches_profiles(&formula(&["!nocheck"]), &nocheck));
+        assert!(!matches_profiles(&formula(&["stage1 cross"]), &nocheck));
+        assert!(matches_profiles(&formula(&["stage1 cross"]), &stage1_cross));
+        assert!(matches_profiles(&formula(&["nocheck", "stage1"]), &nocheck));
+    }
+}
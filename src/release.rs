@@ -0,0 +1,503 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use md5::Md5;
+use sha2::{Digest, Sha256};
+
+use crate::compression::Compression;
+use crate::deb822::parse_paragraphs;
+use crate::error::{Diagnostics, ParseError, ParseErrorKind};
+use crate::index::{Index, Origin};
+use crate::openpgp::Keyring;
+use crate::parse::load_reader;
+
+/// A file listed in the checksum tables of a Release file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReleaseFile {
+    /// Path relative to the directory of the Release file, e.g.
+    /// `main/binary-amd64/Packages.xz`.
+    pub path: String,
+    pub size: u64,
+    pub md5: Option<String>,
+    pub sha256: Option<String>,
+}
+
+impl ReleaseFile {
+    /// Check the size and the strongest listed checksum of the content of the file.
+    pub fn verify(&self, content: &[u8]) -> Result<(), ParseError> {
+        let mismatch = |expected: String, actual: String| {
+            let mismatch = format!("expected {}, got {}", expected, actual);
+            Err(ParseError::new(ParseErrorKind::ChecksumMismatch(mismatch)))
+        };
+        if content.len() as u64 != self.size {
+            return mismatch(
+                format!("{} bytes", self.size),
+                format!("{} bytes", content.len()),
+            );
+        }
+        let (expected, actual) = match (&self.sha256, &self.md5) {
+            (Some(sha256), _) => (sha256, format!("{:x}", Sha256::digest(content))),
+            (None, Some(md5)) => (md5, format!("{:x}", Md5::digest(content))),
+            (None, None) => return Err(ParseError::new(ParseErrorKind::MissingField("SHA256"))),
+        };
+        if *expected != actual {
+            return mismatch(expected.clone(), actual);
+        }
+        Ok(())
+    }
+}
+
+/// A `Release` or `InRelease` file, describing a suite of a repository.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Release {
+    pub origin: String,
+    pub label: String,
+    pub suite: String,
+    pub codename: String,
+    pub version: String,
+    pub date: Option<SystemTime>,
+    /// Date after which the file must not be trusted, as it may have been replaced by one
+    /// listing fixed packages.
+    pub valid_until: Option<SystemTime>,
+    pub architectures: Vec<String>,
+    pub components: Vec<String>,
+    /// Whether packages are only installed on request, see [`Origin::not_automatic`].
+    pub not_automatic: bool,
+    pub but_automatic_upgrades: bool,
+    /// Files listed in the `MD5Sum` and `SHA256` tables, keyed by path.
+    pub files: BTreeMap<String, ReleaseFile>,
+}
+
+impl Release {
+    /// Origin of the packages of a component of the suite.
+    pub fn origin(&self, component: &str) -> Origin {
+        Origin {
+            origin: self.origin.clone(),
+            label: self.label.clone(),
+            suite: self.suite.clone(),
+            codename: self.codename.clone(),
+            version: self.version.clone(),
+            component: component.to_string(),
+            site: String::new(),
+            not_automatic: self.not_automatic,
+            but_automatic_upgrades: self.but_automatic_upgrades,
+        }
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.valid_until
+            .is_some_and(|valid_until| now > valid_until)
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Days between 1970-01-01 and a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Parse a Release file date, in the RFC 2822 format, e.g. `Sat, 10 Feb 2024 09:37:03 UTC`.
+pub fn parse_date(date: &str) -> Option<SystemTime> {
+    let date = date.trim();
+    let date = date.split_once(',').map_or(date, |(_, date)| date);
+    let [day, month, year, time, zone] = date.split_whitespace().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let month = MONTHS.iter().position(|m| *m == month)? as i64 + 1;
+    let days = days_from_civil(year.parse().ok()?, month, day.parse().ok()?);
+    let mut seconds = 0;
+    for part in time.split(':') {
+        seconds = seconds * 60 + part.parse::<i64>().ok()?;
+    }
+    let offset = match zone {
+        "UTC" | "GMT" | "Z" => 0,
+        zone => {
+            let sign = match zone.chars().next()? {
+                '+' => 1,
+                '-' => -1,
+                _ => return None,
+            };
+            let hhmm: i64 = zone.get(1..5)?.parse().ok()?;
+            sign * (hhmm / 100 * 3600 + hhmm % 100 * 60)
+        }
+    };
+    let timestamp = days * 86400 + seconds - offset;
+    Some(UNIX_EPOCH + Duration::from_secs(u64::try_from(timestamp).ok()?))
+}
+
+/// Format a date as in Release files, e.g. `Sat, 10 Feb 2024 09:37:03 UTC`.
+pub fn format_date(date: SystemTime) -> String {
+    let timestamp = date
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let (days, seconds) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    // Inverse of days_from_civil.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    let weekday = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"][days.rem_euclid(7) as usize];
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} UTC",
+        weekday,
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Parse a `Release` file, or an `InRelease` file, whose signature is not checked.
+pub fn parse_release(input: &str) -> Result<Release, ParseError> {
+    let paragraphs = parse_paragraphs(input)?;
+    let paragraph = paragraphs
+        .first()
+        .ok_or_else(|| ParseError::new(ParseErrorKind::MissingField("Suite")))?;
+    let text = |name: &str| paragraph.get_folded(name).unwrap_or_default();
+    let date = |name: &str| match paragraph.get_folded(name) {
+        Some(date) => parse_date(&date).map(Some).ok_or_else(|| {
+            ParseError::new(ParseErrorKind::InvalidValue(date.clone())).in_field(name)
+        }),
+        None => Ok(None),
+    };
+    let mut files: BTreeMap<String, ReleaseFile> = BTreeMap::new();
+    for name in ["MD5Sum", "SHA256"] {
+        for line in paragraph.get_lines(name).unwrap_or_default() {
+            let invalid =
+                || ParseError::new(ParseErrorKind::InvalidValue(line.clone())).in_field(name);
+            let [hash, size, path] = line.split_whitespace().collect::<Vec<_>>()[..] else {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                return Err(invalid());
+            };
+            let size = size.parse().map_err(|_| invalid())?;
+            let file = files
+                .entry(path.to_string())
+                .or_insert_with(|| ReleaseFile {
+                    path: path.to_string(),
+                    size,
+                    ..ReleaseFile::default()
+                });
+            match name {
+                "MD5Sum" => file.md5 = Some(hash.to_string()),
+                _ => file.sha256 = Some(hash.to_string()),
+            }
+        }
+    }
+    Ok(Release {
+        origin: text("Origin"),
+        label: text("Label"),
+        suite: text("Suite"),
+        codename: text("Codename"),
+        version: text("Version"),
+        date: date("Date")?,
+        valid_until: date("Valid-Until")?,
+        architectures: paragraph.get_words("Architectures"),
+        components: paragraph.get_words("Components"),
+        not_automatic: paragraph.get_bool("NotAutomatic").unwrap_or(false),
+        but_automatic_upgrades: paragraph.get_bool("ButAutomaticUpgrades").unwrap_or(false),
+        files,
+    })
+}
+
+/// Load the Packages files of a local mirror of a suite, such as `debian/dists/bookworm`,
+/// into an existing index, returning its Release file.
+///
+/// The suite is described by its `InRelease` file, or its `Release` file if there is none.
+/// For each component and architecture of the index, the Packages file listed there is
+/// verified against its size and checksum before being loaded, preferring compressed
+/// files, and the packages get the suite as origin. Components and architectures without
+/// any of their listed files in the mirror are skipped, as long as one Packages file is.
+/// An expired Release file is reported to the diagnostics. Signatures are not checked, see
+/// [`load_signed_mirror`].
+pub fn load_mirror<P: AsRef<Path>>(
+    index: &mut Index,
+    dist: P,
+    diagnostics: &mut Diagnostics,
+) -> Result<Release, ParseError> {
+    load_suite(index, dist.as_ref(), None, diagnostics)
+}
+
+/// Load a local mirror of a suite as [`load_mirror`] does, once its Release file is found
+/// to be signed by a key of the keyring.
+///
+/// The `InRelease` file is used if there is one, otherwise the `Release` file and its
+/// detached signature `Release.gpg`. Nothing is loaded from a suite whose signature does
+/// not verify, nor from one past its Valid-Until date, even in lenient mode.
+pub fn load_signed_mirror<P: AsRef<Path>>(
+    index: &mut Index,
+    dist: P,
+    keyring: &Keyring,
+    diagnostics: &mut Diagnostics,
+) -> Result<Release, ParseError> {
+    load_suite(index, dist.as_ref(), Some(keyring), diagnostics)
+}
+
+/// Read the Release file of a suite, returning its path and content, which is the signed
+/// content when a keyring is given.
+///
+/// The files are looked for in a directory, with a prefix as in apt's lists directory,
+/// e.g. `deb.debian.org_debian_dists_bookworm_InRelease`.
+pub(crate) fn read_release(
+    dir: &Path,
+    prefix: &str,
+    keyring: Option<&Keyring>,
+) -> Result<(PathBuf, String), ParseError> {
+    let read = |path: &Path| fs::read(path).map_err(|e| ParseError::from(e).in_file(path));
+    let (name, path) = ["InRelease", "Release"]
+        .into_iter()
+        .map(|name| (name, dir.join(format!("{}{}", prefix, name))))
+        .find(|(_, path)| path.is_file())
+        .ok_or_else(|| {
+            let missing = io::Error::new(io::ErrorKind::NotFound, "No InRelease or Release file");
+            ParseError::from(missing).in_file(dir.join(prefix))
+        })?;
+    let content = read(&path)?;
+    if let Some(keyring) = keyring.filter(|_| name == "Release") {
+        let signature = dir.join(format!("{}Release.gpg", prefix));
+        keyring
+            .verify_detached(&content, &read(&signature)?)
+            .map_err(|e| e.in_file(&signature))?;
+    }
+    let content = String::from_utf8(content)
+        .map_err(|_| ParseError::new(ParseErrorKind::InvalidUtf8).in_file(&path))?;
+    let content = match keyring.filter(|_| name == "InRelease") {
+        Some(keyring) => keyring
+            .verify_cleartext(&content)
+            .map_err(|e| e.in_file(&path))?,
+        None => content,
+    };
+    Ok((path, content))
+}
+
+fn load_suite(
+    index: &mut Index,
+    dist: &Path,
+    keyring: Option<&Keyring>,
+    diagnostics: &mut Diagnostics,
+) -> Result<Release, ParseError> {
+    let (path, content) = read_release(dist, "", keyring)?;
+    let release = parse_release(&content).map_err(|e| e.in_file(&path))?;
+    if let Some(valid_until) = release
+        .valid_until
+        .filter(|_| release.is_expired(SystemTime::now()))
+    {
+        let expired = ParseErrorKind::Expired(format_date(valid_until));
+        let error = ParseError::new(expired)
+            .in_file(&path)
+            .in_field("Valid-Until");
+        // As with apt, a signed but expired Release file may be replayed, so it is refused.
+        match keyring {
+            Some(_) => return Err(error),
+            None => diagnostics.report(error)?,
+        }
+    }
+
+    let architectures: Vec<String> = index.architectures().map(String::from).collect();
+    let mut loaded = 0;
+    for component in &release.components {
+        for arch in &architectures {
+            let packages = format!("{}/binary-{}/Packages", component, arch);
+            let listed: Vec<&ReleaseFile> = [
+                Compression::Xz,
+                Compression::Gzip,
+                Compression::Zstd,
+                Compression::Bzip2,
+                Compression::Lz4,
+                Compression::None,
+            ]
+            .iter()
+            .filter_map(|compression| {
+                release
+                    .files
+                    .get(&format!("{}{}", packages, compression.extension()))
+            })
+            .collect();
+            // Partial mirrors leave out components and architectures.
+            let Some(file) = listed.iter().find(|file| dist.join(&file.path).is_file()) else {
+                continue;
+            };
+            loaded += 1;
+            let path = dist.join(&file.path);
+            let content = fs::read(&path).map_err(|e| ParseError::from(e).in_file(&path))?;
+            file.verify(&content).map_err(|e| e.in_file(&path))?;
+            let reported = diagnostics.warnings.len();
+            let origin = release.origin(component);
+            load_reader(index, &content[..], Some(&origin), diagnostics)
+                .map_err(|e| e.in_file(&path))?;
+            for warning in &mut diagnostics.warnings[reported..] {
+                warning.path.get_or_insert(path.clone());
+            }
+        }
+    }
+    if loaded == 0 {
+        let missing = io::Error::new(io::ErrorKind::NotFound, "No Packages file of the suite");
+        return Err(ParseError::from(missing).in_file(dist));
+    }
+    Ok(release)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::deb822::Field;
+    use crate::debian_version::DebianVersion;
+    use crate::error::Mode;
+    use crate::mirror::release_file;
+
+    #[test]
+    fn test_dates() {
+        let date = parse_date("Sat, 10 Feb 2024 09:37:03 UTC").unwrap();
+        assert_eq!(date, UNIX_EPOCH + Duration::from_secs(1707557823));
+        assert_eq!(format_date(date), "Sat, 10 Feb 2024 09:37:03 UTC");
+        assert_eq!(parse_date("Sat, 10 Feb 2024 10:37:03 +0100"), Some(date));
+        assert_eq!(parse_date("10 Feb 2024"), None);
+    }
+
+    #[test]
+    fn test_parse_release() -> Result<(), Box<dyn Error>> {
+        let release = parse_release(
+            r#"-----BEGIN PGP SIGNED MESSAGE-----
+Hash: SHA256
+
+Origin: Debian Backports
+Label: Debian Backports
+Suite: bookworm-backports
+Codename: bookworm-backports
+Date: Sat, 10 Feb 2024 09:37:03 UTC
+Valid-Until: Sat, 17 Feb 2024 09:37:03 UTC
+NotAutomatic: yes
+ButAutomaticUpgrades: yes
+Architectures: all amd64 arm64
+Components: main contrib non-free-firmware
+MD5Sum:
+ 2b2ef3d16d2e3ba7f4a3e0c0e2a3f5a1     1234 main/binary-amd64/Packages
+SHA256:
+ 8f434346648f6b96df89dda901c5176b10a6d83961dd3c1ac88b59b2dc327aa4     1234 main/binary-amd64/Packages
+ e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855        0 main/binary-amd64/Packages.xz
+-----BEGIN PGP SIGNATURE-----
+
+iQIzBAEBCAAdFiEE
+-----END PGP SIGNATURE-----
+"#,
+        )?;
+        assert_eq!(release.suite, "bookworm-backports");
+        assert_eq!(release.components.len(), 3);
+        assert!(release.not_automatic && release.but_automatic_upgrades);
+        assert!(release.is_expired(SystemTime::now()));
+        let packages = &release.files["main/binary-amd64/Packages"];
+        assert_eq!(packages.size, 1234);
+        assert!(packages.md5.is_some() && packages.sha256.is_some());
+        assert_eq!(release.files["main/binary-amd64/Packages.xz"].md5, None);
+        assert!(release.files["main/binary-amd64/Packages.xz"]
+            .verify(b"")
+            .is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_mirror() -> Result<(), Box<dyn Error>> {
+        let tmp = tempfile::tempdir()?;
+        let dist = tmp.path();
+        let packages = "Package: hello\nVersion: 2.10-3\nArchitecture: amd64\n";
+        let field = |name: &str, value: &str| Field {
+            name: name.to_string(),
+            value: value.to_string(),
+        };
+        let release = release_file(
+            vec![
+                field("Suite", "bookworm"),
+                field("Components", "main"),
+                field("Valid-Until", "Sat, 17 Feb 2024 09:37:03 UTC"),
+            ],
+            &[(
+                "main/binary-amd64/Packages".to_string(),
+                packages.as_bytes().to_vec(),
+            )],
+        );
+        fs::create_dir_all(dist.join("main/binary-amd64"))?;
+        fs::write(dist.join("Release"), release.to_string())?;
+        fs::write(dist.join("main/binary-amd64/Packages"), packages)?;
+
+        let mut index = Index::new();
+        let mut diagnostics = Diagnostics::new(Mode::Lenient);
+        assert_eq!(
+            load_mirror(&mut index, dist, &mut diagnostics)?.suite,
+            "bookworm"
+        );
+        let origins = index.origins("hello", &DebianVersion("2.10-3".to_string()));
+        assert_eq!(origins[0].to_string(), "bookworm/main");
+        assert!(matches!(
+            diagnostics.warnings[0].kind,
+            ParseErrorKind::Expired(_)
+        ));
+        assert!(load_mirror(&mut Index::new(), dist, &mut Diagnostics::default()).is_err());
+
+        fs::write(
+            dist.join("main/binary-amd64/Packages"),
+            packages.replace("2.10-3", "2.10-4"),
+        )?;
+        let tampered = load_mirror(&mut Index::new(), dist, &mut diagnostics);
+        assert!(matches!(
+            tampered.unwrap_err().kind,
+            ParseErrorKind::ChecksumMismatch(_)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_partial_mirror() -> Result<(), Box<dyn Error>> {
+        let tmp = tempfile::tempdir()?;
+        let dist = tmp.path();
+        let packages = "Package: hello\nVersion: 2.10-3\nArchitecture: amd64\n";
+        let components = Field {
+            name: "Components".to_string(),
+            value: "main contrib".to_string(),
+        };
+        let release = release_file(
+            vec![components],
+            &[
+                (
+                    "main/binary-amd64/Packages".to_string(),
+                    packages.as_bytes().to_vec(),
+                ),
+                ("contrib/binary-amd64/Packages".to_string(), Vec::new()),
+            ],
+        );
+        fs::create_dir_all(dist.join("main/binary-amd64"))?;
+        fs::write(dist.join("Release"), release.to_string())?;
+        fs::write(dist.join("main/binary-amd64/Packages"), packages)?;
+
+        let mut index = Index::new();
+        load_mirror(&mut index, dist, &mut Diagnostics::default())?;
+        assert_eq!(index.available_versions(&"hello".to_string()).len(), 1);
+
+        fs::remove_file(dist.join("main/binary-amd64/Packages"))?;
+        let empty = load_mirror(&mut Index::new(), dist, &mut Diagnostics::default());
+        assert!(matches!(empty.unwrap_err().kind, ParseErrorKind::Io(_)));
+        Ok(())
+    }
+}
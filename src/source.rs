@@ -0,0 +1,428 @@
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use pubgrub::Range;
+
+use crate::compression;
+use crate::deb822::parse_paragraphs;
+use crate::debian_deps::{Package, Solution, Solver};
+use crate::debian_version::DebianVersion;
+use crate::error::ParseError;
+use crate::index::{self, Index, MultiArch};
+use crate::parse::{
+    convert_dependency_field, paragraph_fields, parse_dependency_field, parse_fields, Dependency,
+};
+
+/// A source package stanza, as found in a `Sources` index or a `debian/control` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourcePackage {
+    pub package: String,
+    pub version: String,
+    pub binary: Vec<String>,
+    pub build_depends: Vec<Dependency>,
+    pub build_depends_indep: Vec<Dependency>,
+    pub build_depends_arch: Vec<Dependency>,
+    pub build_conflicts: Vec<Dependency>,
+    pub build_conflicts_indep: Vec<Dependency>,
+    pub build_conflicts_arch: Vec<Dependency>,
+    /// Substitution variables such as `${misc:Depends}` found in relationship fields.
+    /// They are only known at build time, so they are left out of the relationships.
+    pub substvars: Vec<String>,
+}
+
+/// A binary package stanza of a `debian/control` file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinaryStanza {
+    pub package: String,
+    pub architecture: String,
+}
+
+/// A `debian/control` file: the source stanza followed by the binary stanzas.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceControl {
+    pub source: SourcePackage,
+    pub binaries: Vec<BinaryStanza>,
+}
+
+/// Which binary packages a build produces, as in `dpkg-buildpackage --build`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildType {
+    /// Architecture dependent and independent packages (`full`).
+    Full,
+    /// Architecture dependent packages only (`any`).
+    Arch,
+    /// Architecture independent packages only (`all`).
+    Indep,
+}
+
+/// Parse a single source stanza into a SourcePackage.
+///
+/// The source name is read from `Package`, as in `Sources` indices, or from `Source`,
+/// as in `debian/control`.
+pub fn parse_source_package(stanza: &str) -> Result<SourcePackage, Box<dyn Error>> {
+    source_package_from_fields(parse_fields(stanza)?)
+}
+
+fn source_package_from_fields(
+    mut fields: HashMap<String, String>,
+) -> Result<SourcePackage, Box<dyn Error>> {
+    let package = fields
+        .remove("package")
+        .or_else(|| fields.remove("source"))
+        .ok_or("Missing Package or Source field")?;
+    let version = fields.remove("version").unwrap_or_default();
+    let binary = fields
+        .remove("binary")
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    let mut substvars = Vec::new();
+    let mut relationship = |field: &str| {
+        let value = fields.remove(field).unwrap_or_default();
+        let items: Vec<String> = value
+            .split(',')
+            .filter_map(|item| without_substvars(item, &mut substvars))
+            .collect();
+        // Positions are lost with the substitution variables, so only the field is reported.
+        parse_dependency_field(&items.join(",")).map_err(|e| ParseError {
+            line: 0,
+            column: 0,
+            ..e.in_field(field)
+        })
+    };
+    Ok(SourcePackage {
+        package,
+        version,
+        binary,
+        build_depends: relationship("build-depends")?,
+        build_depends_indep: relationship("build-depends-indep")?,
+        build_depends_arch: relationship("build-depends-arch")?,
+        build_conflicts: relationship("build-conflicts")?,
+        build_conflicts_indep: relationship("build-conflicts-indep")?,
+        build_conflicts_arch: relationship("build-conflicts-arch")?,
+        substvars,
+    })
+}
+
+/// A relationship item without its substitution variables, which are added to `substvars`.
+///
+/// Items that are a substitution variable, such as `${misc:Depends}`, are dropped, as are
+/// version constraints using one, such as in `libhello (= ${binary:Version})`.
+fn without_substvars(item: &str, substvars: &mut Vec<String>) -> Option<String> {
+    let item = item.trim();
+    let mut rest = item;
+    while let Some(start) = rest.find("${") {
+        let end = rest[start..]
+            .find('}')
+            .map_or(rest.len(), |end| start + end + 1);
+        substvars.push(rest[start..end].to_string());
+        rest = &rest[end..];
+    }
+    if item.starts_with("${") && item.ends_with('}') {
+        return None;
+    }
+    match (item.find('('), item.find(')')) {
+        (Some(start), Some(end)) if start < end && item[start..end].contains("${") => {
+            Some(format!("{} {}", &item[..start], &item[end + 1..]))
+        }
+        _ => Some(item.to_string()),
+    }
+}
+
+/// Parse an entire `Sources` index, possibly compressed, into a vector of SourcePackage
+/// entries.
+pub fn parse_debian_sources<P: AsRef<Path>>(path: P) -> Result<Vec<SourcePackage>, Box<dyn Error>> {
+    let content = compression::read_to_string(path)?;
+    let mut sources = Vec::new();
+    for paragraph in parse_paragraphs(&content)? {
+        sources.push(source_package_from_fields(paragraph_fields(&paragraph))?);
+    }
+    Ok(sources)
+}
+
+/// Parse a `debian/control` file.
+///
+/// Comment lines are skipped. As the control file carries no version, it is read from
+/// `debian/changelog` next to it if present.
+pub fn parse_source_control<P: AsRef<Path>>(path: P) -> Result<SourceControl, Box<dyn Error>> {
+    let path = path.as_ref();
+    let mut paragraphs = parse_paragraphs(&fs::read_to_string(path)?)?.into_iter();
+    let source = paragraphs.next().ok_or("Missing source stanza")?;
+    let mut source = source_package_from_fields(paragraph_fields(&source))?;
+    let mut binaries = Vec::new();
+    for paragraph in paragraphs {
+        let mut fields = paragraph_fields(&paragraph);
+        binaries.push(BinaryStanza {
+            package: fields.remove("package").ok_or("Missing Package field")?,
+            architecture: fields.remove("architecture").unwrap_or_default(),
+        });
+    }
+    if source.binary.is_empty() {
+        source.binary = binaries.iter().map(|b| b.package.clone()).collect();
+    }
+    if source.version.is_empty() {
+        let changelog = path.with_file_name("changelog");
+        if let Ok(changelog) = fs::read_to_string(changelog) {
+            source.version = changelog_version(&changelog).unwrap_or_default();
+        }
+    }
+    Ok(SourceControl { source, binaries })
+}
+
+/// The version of the latest entry of a `debian/changelog`,
+/// e.g. "hello (2.10-2) unstable; urgency=medium".
+fn changelog_version(changelog: &str) -> Option<String> {
+    let line = changelog.lines().find(|line| !line.trim().is_empty())?;
+    let start = line.find('(')?;
+    let end = line[start..].find(')')? + start;
+    Some(line[start + 1..end].to_string())
+}
+
+impl SourceControl {
+    /// The build producing every binary package of the control file.
+    pub fn build_type(&self) -> BuildType {
+        let indep = self.binaries.iter().any(|b| b.architecture == "all");
+        let arch = self.binaries.iter().any(|b| b.architecture != "all");
+        match (arch, indep) {
+            (true, false) => BuildType::Arch,
+            (false, true) => BuildType::Indep,
+            _ => BuildType::Full,
+        }
+    }
+}
+
+impl SourcePackage {
+    /// Build dependencies applying to a build, including `build-essential`.
+    pub fn build_dependencies(
+        &self,
+        build: BuildType,
+        arch: &str,
+        profiles: &HashSet<String>,
+    ) -> Vec<index::Dependency> {
+        let mut dependencies = vec![index::Dependency {
+            alternatives: vec![index::Alternative {
+                name: "build-essential".to_string(),
+                range: index::HashedRange(Range::full()),
+                arch: None,
+            }],
+        }];
+        dependencies.extend(convert_dependency_field(
+            &self.build_depends,
+            arch,
+            profiles,
+        ));
+        if build != BuildType::Indep {
+            dependencies.extend(convert_dependency_field(
+                &self.build_depends_arch,
+                arch,
+                profiles,
+            ));
+        }
+        if build != BuildType::Arch {
+            dependencies.extend(convert_dependency_field(
+                &self.build_depends_indep,
+                arch,
+                profiles,
+            ));
+        }
+        dependencies
+    }
+
+    /// Build conflicts applying to a build.
+    pub fn build_conflicts(
+        &self,
+        build: BuildType,
+        arch: &str,
+        profiles: &HashSet<String>,
+    ) -> Vec<index::Alternative> {
+        let mut fields = vec![&self.build_conflicts];
+        if build != BuildType::Indep {
+            fields.push(&self.build_conflicts_arch);
+        }
+        if build != BuildType::Arch {
+            fields.push(&self.build_conflicts_indep);
+        }
+        fields
+            .into_iter()
+            .flat_map(|field| convert_dependency_field(field, arch, profiles))
+            .flat_map(|dependency| dependency.alternatives)
+            .collect()
+    }
+}
+
+/// Register a `<source>-build-deps` package carrying the build relationships of a source
+/// package, as `mk-build-deps` does, and return the Root package requiring it.
+pub fn build_deps_root(
+    index: &mut Index,
+    source: &SourcePackage,
+    build: BuildType,
+    profiles: &HashSet<String>,
+) -> Package {
+    let arch = index.native_arch.clone();
+    let name = format!("{}-build-deps", source.package);
+    // An empty version would be taken for the not installed sentinel.
+    let version = match source.version.as_str() {
+        "" => DebianVersion("0".to_string()),
+        version => DebianVersion(version.to_string()),
+    };
+    index.add_deps(
+        &name,
+        version.clone(),
+        source.build_dependencies(build, &arch, profiles),
+    );
+    index.add_conflicts(
+        &name,
+        version.clone(),
+        source.build_conflicts(build, &arch, profiles),
+        Vec::new(),
+    );
+    index.add_arch(&name, version.clone(), &arch, MultiArch::No);
+    Package::Root(vec![(Package::Base(name), Range::singleton(version))])
+}
+
+/// Resolve the build environment of a source package against a binary index.
+pub fn solve_build_deps(
+    index: &mut Index,
+    source: &SourcePackage,
+    build: BuildType,
+    profiles: &HashSet<String>,
+) -> Result<Solution, Box<dyn Error>> {
+    let root = build_deps_root(index, source, build, profiles);
+    Solver::from(&*index).resolve(root, DebianVersion("".to_string()))
+}
+
+/// Resolve the packages needed to build a source tree from its parsed `debian/control`,
+/// like `apt-get build-dep .`.
+pub fn solve_source_control(
+    index: &mut Index,
+    control: &SourceControl,
+    profiles: &HashSet<String>,
+) -> Result<Solution, Box<dyn Error>> {
+    solve_build_deps(index, &control.source, control.build_type(), profiles)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debian_deps::not_installed;
+    use crate::index::{Alternative, HashedRange};
+
+    const SOURCE: &str = r#"Package: hello
+Binary: hello, hello-doc
+Version: 2.10-2
+Architecture: any all
+Build-Depends: debhelper-compat (= 12), libcheck-dev <!nocheck>
+Build-Depends-Indep: texinfo
+Build-Conflicts: autoconf2.13
+"#;
+
+    fn v(version: &str) -> DebianVersion {
+        DebianVersion(version.to_string())
+    }
+
+    fn dep(name: &str) -> index::Dependency {
+        index::Dependency {
+            alternatives: vec![Alternative {
+                name: name.to_string(),
+                range: HashedRange(Range::full()),
+                arch: None,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_parse_source_package() -> Result<(), Box<dyn Error>> {
+        let source = parse_source_package(SOURCE)?;
+        assert_eq!(source.package, "hello");
+        assert_eq!(source.binary, vec!["hello", "hello-doc"]);
+        assert_eq!(source.build_depends.len(), 2);
+        assert_eq!(source.build_depends_indep.len(), 1);
+        assert_eq!(source.build_conflicts.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_source_control() -> Result<(), Box<dyn Error>> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path();
+        fs::write(
+            dir.join("control"),
+            r#"Source: hello
+# Build-Depends are kept in sync with upstream.
+Build-Depends: debhelper-compat (= 12), ${misc:Pre-Depends},
+ libhello-dev (>= ${source:Upstream-Version}) [linux-any]
+
+Package: hello
+Architecture: any
+Depends: ${shlibs:Depends}, ${misc:Depends}
+"#,
+        )?;
+        fs::write(
+            dir.join("changelog"),
+            "hello (2.10-2) unstable; urgency=medium\n\n  * Release.\n",
+        )?;
+        let control = parse_source_control(dir.join("control"))?;
+        assert_eq!(control.source.package, "hello");
+        assert_eq!(control.source.version, "2.10-2");
+        assert_eq!(control.source.binary, vec!["hello"]);
+        let libhello = &control.source.build_depends[1].alternatives[0];
+        assert_eq!(libhello.package, "libhello-dev");
+        assert_eq!(libhello.version_constraint, None);
+        assert!(libhello.arch.is_some());
+        assert_eq!(
+            control.source.substvars,
+            vec!["${misc:Pre-Depends}", "${source:Upstream-Version}"]
+        );
+        assert_eq!(control.build_type(), BuildType::Arch);
+        Ok(())
+    }
+
+    #[test]
+    fn test_solve_build_deps() -> Result<(), Box<dyn Error>> {
+        let source = parse_source_package(SOURCE)?;
+        let mut index = Index::new();
+        index.add_deps("build-essential", v("12.6"), vec![dep("gcc")]);
+        index.add_deps("gcc", v("4:8.3.0-1"), vec![]);
+        index.add_deps("debhelper", v("12.1.1"), vec![]);
+        index.add_provider("debhelper-compat", "debhelper", v("12.1.1"), Some(v("12")));
+        index.add_deps("libcheck-dev", v("0.10.0-3"), vec![]);
+        index.add_deps("texinfo", v("6.5.0"), vec![]);
+        index.add_deps("autoconf2.13", v("2.13-68"), vec![]);
+        index.add_deps("gnulib", v("1"), vec![dep("autoconf2.13")]);
+
+        let nocheck = ["nocheck".to_string()].into();
+        let sol = solve_build_deps(&mut index, &source, BuildType::Arch, &nocheck)?;
+        let mut installed: Vec<String> = sol
+            .into_iter()
+            .filter_map(|(package, version)| match package {
+                Package::Base(name) if version != not_installed() => Some(name),
+                _ => None,
+            })
+            .collect();
+        installed.sort();
+        assert_eq!(
+            installed,
+            vec!["build-essential", "debhelper", "gcc", "hello-build-deps"]
+        );
+
+        let sol = solve_build_deps(&mut index, &source, BuildType::Full, &HashSet::new())?;
+        assert!(sol.contains_key(&Package::Base("libcheck-dev".to_string())));
+        assert!(sol.contains_key(&Package::Base("texinfo".to_string())));
+
+        // Sources without a version, such as a debian/control without a changelog.
+        let unversioned = SourcePackage {
+            version: String::new(),
+            ..source
+        };
+        let sol = solve_build_deps(&mut index, &unversioned, BuildType::Arch, &nocheck)?;
+        assert_eq!(
+            sol.get(&Package::Base("hello-build-deps".to_string())),
+            Some(&v("0"))
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::deb822::paragraphs;
+use crate::debian_deps::{not_installed, Package, Solution};
+use crate::debian_version::DebianVersion;
+use crate::error::{Diagnostics, ParseError, ParseErrorKind};
+use crate::index::{Index, PackageName};
+use crate::parse::{add_debian_package, debian_package_from_paragraph};
+
+/// The selection state of a package, the first word of the `Status` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Want {
+    Unknown,
+    Install,
+    Hold,
+    Deinstall,
+    Purge,
+}
+
+/// Whether a package needs to be reinstalled, the second word of the `Status` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flag {
+    Ok,
+    Reinstreq,
+}
+
+/// The installation state of a package, the third word of the `Status` field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    NotInstalled,
+    ConfigFiles,
+    HalfInstalled,
+    Unpacked,
+    HalfConfigured,
+    TriggersAwaited,
+    TriggersPending,
+    Installed,
+}
+
+/// The `Status` field of a dpkg status database entry, e.g. "install ok installed".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status {
+    pub want: Want,
+    pub flag: Flag,
+    pub state: State,
+}
+
+impl FromStr for Want {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "unknown" => Ok(Want::Unknown),
+            "install" => Ok(Want::Install),
+            "hold" => Ok(Want::Hold),
+            "deinstall" => Ok(Want::Deinstall),
+            "purge" => Ok(Want::Purge),
+            _ => Err(format!("Unknown selection state: {}", s)),
+        }
+    }
+}
+
+impl FromStr for Flag {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ok" => Ok(Flag::Ok),
+            "reinstreq" => Ok(Flag::Reinstreq),
+            _ => Err(format!("Unknown status flag: {}", s)),
+        }
+    }
+}
+
+impl FromStr for State {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "not-installed" => Ok(State::NotInstalled),
+            "config-files" => Ok(State::ConfigFiles),
+            "half-installed" => Ok(State::HalfInstalled),
+            "unpacked" => Ok(State::Unpacked),
+            "half-configured" => Ok(State::HalfConfigured),
+            "triggers-awaited" => Ok(State::TriggersAwaited),
+            "triggers-pending" => Ok(State::TriggersPending),
+            "installed" => Ok(State::Installed),
+            _ => Err(format!("Unknown package state: {}", s)),
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<_>>()[..] {
+            [want, flag, state] => Ok(Status {
+                want: want.parse()?,
+                flag: flag.parse()?,
+                state: state.parse()?,
+            }),
+            _ => Err(format!("Invalid status: {}", s)),
+        }
+    }
+}
+
+impl Status {
+    /// Whether the files of the package are on the system.
+    ///
+    /// As in apt, such packages count as installed, even if they are not fully configured.
+    pub fn files_present(&self) -> bool {
+        !matches!(self.state, State::NotInstalled | State::ConfigFiles)
+    }
+}
+
+/// An entry of the dpkg status database.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstalledPackage {
+    /// The architecture-qualified name, as the package is keyed in the [`Index`].
+    pub name: PackageName,
+    pub version: DebianVersion,
+    pub status: Status,
+}
+
+/// The packages known to dpkg on a system, keyed as in the [`Index`].
+#[derive(Debug, Clone, Default)]
+pub struct InstalledSet {
+    pub packages: BTreeMap<PackageName, InstalledPackage>,
+}
+
+impl InstalledSet {
+    pub fn new() -> Self {
+        InstalledSet::default()
+    }
+
+    pub fn insert(&mut self, package: InstalledPackage) {
+        self.packages.insert(package.name.clone(), package);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&InstalledPackage> {
+        self.packages.get(name)
+    }
+
+    /// The installed version of a package, if its files are on the system.
+    pub fn version(&self, name: &str) -> Option<&DebianVersion> {
+        self.get(name)
+            .filter(|package| package.status.files_present())
+            .map(|package| &package.version)
+    }
+
+    /// The packages whose files are on the system.
+    pub fn installed(&self) -> impl Iterator<Item = &InstalledPackage> {
+        self.packages
+            .values()
+            .filter(|package| package.status.files_present())
+    }
+
+    /// The packages of a solution, as installed by it, e.g. to move a system built from one
+    /// release to the next.
+    pub fn from_solution(solution: &Solution) -> Self {
+        let mut installed = InstalledSet::new();
+        for (package, version) in solution {
+            match package {
+                Package::Base(name) if *version != not_installed() => {
+                    installed.insert(InstalledPackage {
+                        name: name.clone(),
+                        version: version.clone(),
+                        status: Status {
+                            want: Want::Install,
+                            flag: Flag::Ok,
+                            state: State::Installed,
+                        },
+                    })
+                }
+                _ => continue,
+            }
+        }
+        installed
+    }
+
+    /// The packages on hold, which should be kept at their installed version.
+    pub fn held(&self) -> impl Iterator<Item = &InstalledPackage> {
+        self.packages
+            .values()
+            .filter(|package| package.status.want == Want::Hold)
+    }
+}
+
+/// Load a dpkg status database, such as `/var/lib/dpkg/status`.
+///
+/// Packages whose files are on the system are also added to the index, so that installed
+/// versions no longer available from any repository are still known to the solver.
+/// Entries of purged packages, which have no version, are skipped.
+pub fn load_status<P: AsRef<Path>>(
+    index: &mut Index,
+    path: P,
+    diagnostics: &mut Diagnostics,
+) -> Result<InstalledSet, ParseError> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).map_err(|e| ParseError::from(e).in_file(path))?;
+    let mut installed = InstalledSet::new();
+    let reported = diagnostics.warnings.len();
+    for (stanza, paragraph) in paragraphs(&content).enumerate() {
+        let entry = paragraph.and_then(|paragraph| {
+            let line = paragraph.fields()[0].line;
+            let status = paragraph
+                .get_folded("Status")
+                .ok_or(ParseErrorKind::MissingField("Status"))
+                .and_then(|status| {
+                    status
+                        .parse::<Status>()
+                        .map_err(ParseErrorKind::InvalidValue)
+                })
+                .map_err(|kind| ParseError::new(kind).in_stanza(stanza).at(line, 1))?;
+            if status.state == State::NotInstalled {
+                return Ok(None);
+            }
+            let dp = debian_package_from_paragraph(&paragraph, stanza, diagnostics)?;
+            let name = index.qualify(&dp.package, dp.architecture.as_deref().unwrap_or(""));
+            let version = DebianVersion(dp.version.clone());
+            if status.files_present() {
+                add_debian_package(index, dp, None);
+            }
+            Ok(Some(InstalledPackage {
+                name,
+                version,
+                status,
+            }))
+        });
+        match entry {
+            Ok(Some(package)) => installed.insert(package),
+            Ok(None) => {}
+            Err(e) => diagnostics.report(e).map_err(|e| e.in_file(path))?,
+        }
+    }
+    for warning in &mut diagnostics.warnings[reported..] {
+        warning.path.get_or_insert(path.to_path_buf());
+    }
+    Ok(installed)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::error::Error;
+
+    use super::*;
+    use crate::error::Mode;
+
+    const STATUS: &str = "Package: libc6
+Status: install ok installed
+Priority: optional
+Architecture: amd64
+Multi-Arch: same
+Version: 2.36-9
+Depends: libgcc-s1
+
+Package: libc6
+Status: install ok installed
+Architecture: i386
+Multi-Arch: same
+Version: 2.36-9
+
+Package: libgcc-s1
+Status: hold ok half-configured
+Architecture: amd64
+Version: 12.2.0-14
+
+Package: old
+Status: deinstall ok config-files
+Architecture: amd64
+Version: 1.0
+
+Package: gone
+Status: purge ok not-installed
+Architecture: amd64
+
+Package: broken
+Status: install ok
+Version: 1
+";
+
+    #[test]
+    fn test_parse_status() {
+        assert_eq!(
+            "install ok installed".parse::<Status>(),
+            Ok(Status {
+                want: Want::Install,
+                flag: Flag::Ok,
+                state: State::Installed,
+            })
+        );
+        assert!("install ok".parse::<Status>().is_err());
+        assert!("install ok configured".parse::<Status>().is_err());
+    }
+
+    #[test]
+    fn test_load_status() -> Result<(), Box<dyn Error>> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("status");
+        fs::write(&path, STATUS)?;
+        let mut index = Index::new();
+        index.set_architectures("amd64", &["i386"]);
+        let mut diagnostics = Diagnostics::new(Mode::Lenient);
+        let installed = load_status(&mut index, &path, &mut diagnostics)?;
+
+        let v = |version: &str| DebianVersion(version.to_string());
+        assert_eq!(installed.version("libc6"), Some(&v("2.36-9")));
+        assert_eq!(installed.version("libc6:i386"), Some(&v("2.36-9")));
+        assert_eq!(installed.version("libgcc-s1"), Some(&v("12.2.0-14")));
+        assert_eq!(installed.version("old"), None);
+        assert_eq!(
+            installed.get("old").map(|p| p.status.state),
+            Some(State::ConfigFiles)
+        );
+        assert!(installed.get("gone").is_none());
+        assert_eq!(
+            installed
+                .held()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["libgcc-s1"]
+        );
+        assert_eq!(installed.installed().count(), 3);
+
+        let mut names: Vec<_> = index.packages.keys().collect();
+        names.sort();
+        assert_eq!(names, vec!["libc6", "libc6:i386", "libgcc-s1"]);
+
+        assert_eq!(diagnostics.warnings.len(), 1);
+        assert_eq!(diagnostics.warnings[0].stanza, Some(5));
+        Ok(())
+    }
+}
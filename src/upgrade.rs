@@ -0,0 +1,439 @@
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+use pubgrub::Range;
+
+use crate::debian_deps::{not_installed, Package, Solution, SolveOptions, Solver};
+use crate::debian_version::DebianVersion;
+use crate::index::{Alternative, Dependency, HashedRange, Index, PackageName};
+use crate::status::{InstalledPackage, InstalledSet};
+
+/// How installed packages may change when solving against an installed system.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpgradeMode {
+    /// Install the requested packages, keeping installed packages at their installed
+    /// version where possible, as `apt-get install`.
+    MinimalChange,
+    /// Move installed packages to their candidate versions, see
+    /// [`Index::candidate_versions`], without removing any of them, as `apt upgrade`.
+    Upgrade,
+    /// Move installed packages to their candidate versions, removing packages if required,
+    /// as `apt-get dist-upgrade`.
+    DistUpgrade,
+}
+
+/// A change to an installed system.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Install(PackageName, DebianVersion),
+    Upgrade(PackageName, DebianVersion, DebianVersion),
+    Downgrade(PackageName, DebianVersion, DebianVersion),
+    Remove(PackageName, DebianVersion),
+}
+
+impl Change {
+    pub fn package(&self) -> &str {
+        match self {
+            Change::Install(name, _)
+            | Change::Upgrade(name, _, _)
+            | Change::Downgrade(name, _, _)
+            | Change::Remove(name, _) => name,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Install(name, version) => write!(f, "install {} {}", name, version),
+            Change::Upgrade(name, from, to) => write!(f, "upgrade {} {} -> {}", name, from, to),
+            Change::Downgrade(name, from, to) => {
+                write!(f, "downgrade {} {} -> {}", name, from, to)
+            }
+            Change::Remove(name, version) => write!(f, "remove {} {}", name, version),
+        }
+    }
+}
+
+/// The changes turning the installed set into a solution, ordered by package name.
+pub fn delta(installed: &InstalledSet, solution: &Solution) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for (package, version) in solution {
+        let Package::Base(name) = package else {
+            continue;
+        };
+        let change = match installed.version(name) {
+            Some(from) if *version == not_installed() => Change::Remove(name.clone(), from.clone()),
+            _ if *version == not_installed() => continue,
+            None => Change::Install(name.clone(), version.clone()),
+            Some(from) if from < version => {
+                Change::Upgrade(name.clone(), from.clone(), version.clone())
+            }
+            Some(from) if from > version => {
+                Change::Downgrade(name.clone(), from.clone(), version.clone())
+            }
+            Some(_) => continue,
+        };
+        changes.push(change);
+    }
+    // Packages that may be removed are kept through an Optional, which is left
+    // unsatisfied when they are removed.
+    for (package, version) in solution {
+        let Package::Optional(dependency) = package else {
+            continue;
+        };
+        let [alternative] = &dependency.alternatives[..] else {
+            continue;
+        };
+        if *version != not_installed()
+            || solution.contains_key(&Package::Base(alternative.name.clone()))
+        {
+            continue;
+        }
+        if let Some(from) = installed.version(&alternative.name) {
+            changes.push(Change::Remove(alternative.name.clone(), from.clone()));
+        }
+    }
+    changes.sort_by(|a, b| a.package().cmp(b.package()));
+    changes.dedup();
+    changes
+}
+
+/// Solve for the requested packages on top of an installed system, returning the changes
+/// to make.
+///
+/// Every installed package known to the index is kept in the solution, so that the
+/// dependencies of those that are not upgraded still hold. Only a dist-upgrade, or a request
+/// removing packages as [`crate::debian_deps::removed`] does, may remove them, as `apt-get remove`
+/// also removes the packages depending on those removed. Held packages stay at their
+/// installed version, unless requested.
+pub fn solve_upgrade(
+    index: &Index,
+    installed: &InstalledSet,
+    mode: UpgradeMode,
+    requests: Vec<(Package, Range<DebianVersion>)>,
+) -> Result<Vec<Change>, Box<dyn Error>> {
+    let solution = resolve_upgrade(index, installed, mode, requests)?;
+    Ok(delta(installed, &solution))
+}
+
+/// Resolve as [`solve_upgrade`] does, explaining the failure if there is no solution.
+fn resolve_upgrade(
+    index: &Index,
+    installed: &InstalledSet,
+    mode: UpgradeMode,
+    requests: Vec<(Package, Range<DebianVersion>)>,
+) -> Result<Solution, Box<dyn Error>> {
+    let removing = requests
+        .iter()
+        .any(|(_, range)| range.contains(&not_installed()) && *range != Range::full());
+    let mut requirements = requests;
+    let mut options = SolveOptions {
+        installed: installed
+            .installed()
+            .filter(|package| index.packages.contains_key(&package.name))
+            .map(|package| (package.name.clone(), package.version.clone()))
+            .collect(),
+        ..SolveOptions::default()
+    };
+    let held: HashSet<&str> = installed
+        .held()
+        .map(|package| package.name.as_str())
+        .collect();
+    for package in installed.installed() {
+        if !index.packages.contains_key(&package.name) {
+            continue;
+        }
+        let name = Package::Base(package.name.clone());
+        if requirements.iter().any(|(requested, _)| *requested == name) {
+            // Requested packages move to their candidate, as with `apt-get install`.
+            continue;
+        } else if held.contains(package.name.as_str()) {
+            requirements.push((name, Range::singleton(package.version.clone())));
+        } else if mode == UpgradeMode::DistUpgrade || removing {
+            let keep = Dependency {
+                alternatives: vec![Alternative {
+                    name: package.name.clone(),
+                    range: HashedRange(Range::full()),
+                    arch: None,
+                }],
+            };
+            requirements.push((Package::Optional(keep), Range::full()));
+        } else {
+            requirements.push((name, Range::full()));
+        }
+        let version = match mode {
+            UpgradeMode::MinimalChange => package.version.clone(),
+            UpgradeMode::Upgrade | UpgradeMode::DistUpgrade => {
+                match index.candidate(&package.name, Some(&package.version)) {
+                    Some(candidate) => candidate,
+                    None => continue,
+                }
+            }
+        };
+        options.preferred.insert(package.name.clone(), version);
+    }
+
+    Solver::new(index, options).resolve(Package::Root(requirements), not_installed())
+}
+
+/// An installed package that a release upgrade removes.
+#[derive(Debug, Clone)]
+pub struct Removal {
+    pub package: PackageName,
+    pub version: DebianVersion,
+    /// Explanation of why the package can not be kept.
+    pub reason: String,
+}
+
+/// The outcome of moving an installed system to another release.
+#[derive(Debug, Clone)]
+pub struct ReleaseUpgrade {
+    /// The packages installed after the upgrade, obsolete packages aside.
+    pub packages: BTreeMap<PackageName, DebianVersion>,
+    pub changes: Vec<Change>,
+    pub removals: Vec<Removal>,
+    /// Installed packages the target release no longer ships. They are left installed, but
+    /// their relationships are not checked.
+    pub obsolete: Vec<InstalledPackage>,
+}
+
+/// The packages installed by a solution.
+fn installed_packages(solution: &Solution) -> BTreeMap<PackageName, DebianVersion> {
+    solution
+        .iter()
+        .filter_map(|(package, version)| match package {
+            Package::Base(name) if *version != not_installed() => {
+                Some((name.clone(), version.clone()))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Simulate a dist-upgrade of an installed system, from a status file or a solution for the
+/// current release, to the release of `target`.
+///
+/// Installed packages are removed only if they can not be kept, and each removal comes with
+/// the explanation of why. If no system can be built at all, e.g. because a held package
+/// can not be kept, the explanation is the error.
+///
+/// Each removal is checked by resolving the whole system again with the package required,
+/// so a release upgrade removing `n` packages takes at least `n + 1` resolutions. When the
+/// package can be kept after all, that system replaces the upgraded one and the remaining
+/// removals are checked against it.
+pub fn simulate_release_upgrade(
+    target: &Index,
+    installed: &InstalledSet,
+) -> Result<ReleaseUpgrade, Box<dyn Error>> {
+    let mut solution = resolve_upgrade(target, installed, UpgradeMode::DistUpgrade, vec![])?;
+    let mut removals: Vec<Removal> = Vec::new();
+    loop {
+        let unchecked = delta(installed, &solution)
+            .into_iter()
+            .find_map(|change| match change {
+                Change::Remove(name, version)
+                    if !removals.iter().any(|removal| removal.package == name) =>
+                {
+                    Some((name, version))
+                }
+                _ => None,
+            });
+        let Some((name, version)) = unchecked else {
+            break;
+        };
+        // Require the package on top of the upgraded system.
+        let mut keep: Vec<_> = installed_packages(&solution)
+            .into_iter()
+            .map(|(name, version)| (Package::Base(name), Range::singleton(version)))
+            .collect();
+        keep.push((Package::Base(name.clone()), Range::full()));
+        match resolve_upgrade(target, installed, UpgradeMode::DistUpgrade, keep) {
+            Ok(kept) => solution = kept,
+            Err(err) => removals.push(Removal {
+                package: name,
+                version,
+                reason: err.to_string(),
+            }),
+        }
+    }
+    let changes = delta(installed, &solution);
+    let packages = installed_packages(&solution);
+    let obsolete = installed
+        .installed()
+        .filter(|package| !target.packages.contains_key(&package.name))
+        .cloned()
+        .collect();
+    Ok(ReleaseUpgrade {
+        packages,
+        changes,
+        removals,
+        obsolete,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debian_deps::removed;
+    use crate::status::{Flag, State, Status, Want};
+    use std::error::Error;
+
+    fn v(version: &str) -> DebianVersion {
+        DebianVersion(version.to_string())
+    }
+
+    fn dep(name: &str, range: Range<DebianVersion>) -> Dependency {
+        Dependency {
+            alternatives: vec![Alternative {
+                name: name.to_string(),
+                range: HashedRange(range),
+                arch: None,
+            }],
+        }
+    }
+
+    fn installed_set(packages: &[(&str, &str, Want)]) -> InstalledSet {
+        let mut installed = InstalledSet::new();
+        for (name, version, want) in packages {
+            installed.insert(InstalledPackage {
+                name: name.to_string(),
+                version: v(version),
+                status: Status {
+                    want: *want,
+                    flag: Flag::Ok,
+                    state: State::Installed,
+                },
+            });
+        }
+        installed
+    }
+
+    /// `app` 1 is installed along with `lib` 1, which `app` 2 no longer accepts.
+    /// `tool` 2 needs `lib` 2, and `old` conflicts with `lib` 2.
+    fn index() -> Index {
+        let mut index = Index::new();
+        index.add_deps("lib", v("1"), vec![]);
+        index.add_deps("lib", v("2"), vec![]);
+        index.add_deps("app", v("1"), vec![dep("lib", Range::singleton(v("1")))]);
+        index.add_deps("app", v("2"), vec![dep("lib", Range::singleton(v("2")))]);
+        index.add_deps("tool", v("1"), vec![]);
+        index.add_deps("tool", v("2"), vec![dep("lib", Range::higher_than(v("2")))]);
+        index.add_deps("old", v("1"), vec![]);
+        index.add_conflicts(
+            "old",
+            v("1"),
+            vec![Alternative {
+                name: "lib".to_string(),
+                range: HashedRange(Range::higher_than(v("2"))),
+                arch: None,
+            }],
+            vec![],
+        );
+        index
+    }
+
+    #[test]
+    fn test_minimal_change() -> Result<(), Box<dyn Error>> {
+        let index = index();
+        let installed = installed_set(&[("app", "1", Want::Install), ("lib", "1", Want::Install)]);
+        let tool = vec![(Package::Base("tool".to_string()), Range::full())];
+        let changes = solve_upgrade(&index, &installed, UpgradeMode::MinimalChange, tool)?;
+        assert_eq!(changes, vec![Change::Install("tool".to_string(), v("1"))]);
+
+        let tool = vec![(Package::Base("tool".to_string()), Range::singleton(v("2")))];
+        let changes = solve_upgrade(&index, &installed, UpgradeMode::MinimalChange, tool)?;
+        assert_eq!(
+            changes,
+            vec![
+                Change::Upgrade("app".to_string(), v("1"), v("2")),
+                Change::Upgrade("lib".to_string(), v("1"), v("2")),
+                Change::Install("tool".to_string(), v("2")),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade() -> Result<(), Box<dyn Error>> {
+        let index = index();
+        let installed = installed_set(&[
+            ("app", "1", Want::Install),
+            ("lib", "1", Want::Install),
+            ("old", "1", Want::Install),
+        ]);
+        // Upgrading lib would remove old, so app and lib are kept back.
+        let changes = solve_upgrade(&index, &installed, UpgradeMode::Upgrade, vec![])?;
+        assert_eq!(changes, vec![]);
+
+        // Upgrades are decided before keeping old, see `Solver::prioritize`.
+        let changes = solve_upgrade(&index, &installed, UpgradeMode::DistUpgrade, vec![])?;
+        assert_eq!(
+            changes,
+            vec![
+                Change::Upgrade("app".to_string(), v("1"), v("2")),
+                Change::Upgrade("lib".to_string(), v("1"), v("2")),
+                Change::Remove("old".to_string(), v("1")),
+            ]
+        );
+
+        let held = installed_set(&[
+            ("app", "1", Want::Install),
+            ("lib", "1", Want::Hold),
+            ("old", "1", Want::Install),
+        ]);
+        let changes = solve_upgrade(&index, &held, UpgradeMode::DistUpgrade, vec![])?;
+        assert_eq!(changes, vec![]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove() -> Result<(), Box<dyn Error>> {
+        let index = index();
+        // Removing lib removes app, which depends on it.
+        let installed = installed_set(&[("app", "1", Want::Install), ("lib", "1", Want::Install)]);
+        let lib = vec![(Package::Base("lib".to_string()), removed())];
+        let changes = solve_upgrade(&index, &installed, UpgradeMode::MinimalChange, lib)?;
+        assert_eq!(
+            changes,
+            vec![
+                Change::Remove("app".to_string(), v("1")),
+                Change::Remove("lib".to_string(), v("1")),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_release_upgrade() -> Result<(), Box<dyn Error>> {
+        let mut buster = Index::new();
+        buster.add_deps("lib", v("1"), vec![]);
+        buster.add_deps("app", v("1"), vec![dep("lib", Range::full())]);
+        buster.add_deps("old", v("1"), vec![]);
+        buster.add_deps("legacy", v("1"), vec![]);
+        let root = Package::Root(
+            ["app", "old", "legacy"]
+                .into_iter()
+                .map(|name| (Package::Base(name.to_string()), Range::full()))
+                .collect(),
+        );
+        let solution = Solver::from(&buster).resolve(root, not_installed())?;
+        let installed = InstalledSet::from_solution(&solution);
+        assert_eq!(installed.packages.len(), 4);
+
+        // old no longer works with lib 2 and legacy is gone.
+        let bullseye = index();
+        let upgrade = simulate_release_upgrade(&bullseye, &installed)?;
+        assert_eq!(
+            upgrade.packages.into_iter().collect::<Vec<_>>(),
+            vec![("app".to_string(), v("2")), ("lib".to_string(), v("2"))]
+        );
+        assert_eq!(upgrade.removals.len(), 1);
+        assert_eq!(upgrade.removals[0].package, "old");
+        assert!(upgrade.removals[0].reason.contains("old"));
+        let obsolete: Vec<&str> = upgrade.obsolete.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(obsolete, vec!["legacy"]);
+        Ok(())
+    }
+}